@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use qa_parser::{format_document, AccountSyntax, Parser};
+
+// Every input has to give us either a syntax tree or an error, never a panic, and anything we
+// can parse has to survive being formatted and parsed again
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        for syntax in &[AccountSyntax::Digits, AccountSyntax::Alphanumeric] {
+            if let Ok(document) = Parser::new(input).account_syntax(*syntax).parse_document() {
+                let formatted = format_document(&document);
+                let reparsed = Parser::new(&formatted)
+                    .account_syntax(*syntax)
+                    .parse_document()
+                    .expect("formatted output must parse");
+                assert_eq!(document, reparsed);
+            }
+            let _ = Parser::new(input).account_syntax(*syntax).parse_recovering();
+        }
+    }
+});
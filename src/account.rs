@@ -0,0 +1,214 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// An account identifier like `3000`, `0150`, `1920.01` or `A-4000`.
+///
+/// The identifier is kept exactly as it's written so `0150` and `150` are different accounts. It
+/// starts with a letter or a digit, and `.`, `-` and `_` can separate the letters and digits.
+///
+/// Accounts are ordered the way account codes are usually sorted: runs of digits are compared by
+/// their value and everything else character by character. So `999` comes before `3000`, `0150`
+/// is between `100` and `200` and `1920.2` comes before `1920.10`. Accounts that only differ in
+/// leading zeros are ordered by their text, `0150` before `150`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct AccountId(String);
+
+impl AccountId {
+    /// Creates an account without checking that it's a valid identifier, the caller makes sure it
+    /// is.
+    pub(crate) fn new(text: String) -> Self {
+        AccountId(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns true if `c` can be part of an account identifier. Only letters and digits can
+    /// start or end one.
+    pub(crate) fn is_account_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || AccountId::is_separator(c)
+    }
+
+    fn is_separator(c: char) -> bool {
+        c == '.' || c == '-' || c == '_'
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<u32> for AccountId {
+    fn from(number: u32) -> Self {
+        AccountId(number.to_string())
+    }
+}
+
+/// The account identifiers the parser accepts in a report definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccountSyntax {
+    /// Account numbers with only digits like `3000` or `0150`. This is the default, it catches
+    /// typos like `6020.6100` for `6020..6100`.
+    #[default]
+    Digits,
+    /// Account codes with letters and digits, and `.`, `-` or `_` between them, like `1920.01` or
+    /// `A-4000`.
+    Alphanumeric,
+}
+
+/// The error returned when parsing an `AccountId` fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAccountError(String);
+
+impl fmt::Display for ParseAccountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid account \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseAccountError {}
+
+/// Parses account identifiers like `3000` or `A-4000`. Separators have to be between two letters
+/// or digits, so `A-`, `-4000` and `1920..01` are errors.
+impl FromStr for AccountId {
+    type Err = ParseAccountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut previous = None;
+        for c in s.chars() {
+            let valid = match previous {
+                None => c.is_ascii_alphanumeric(),
+                Some(p) if AccountId::is_separator(p) => c.is_ascii_alphanumeric(),
+                Some(_) => AccountId::is_account_char(c),
+            };
+            if !valid {
+                return Err(ParseAccountError(s.to_string()));
+            }
+            previous = Some(c);
+        }
+
+        match previous {
+            Some(last) if last.is_ascii_alphanumeric() => Ok(AccountId(s.to_string())),
+            _ => Err(ParseAccountError(s.to_string())),
+        }
+    }
+}
+
+/// Accounts are written as strings but numbers are accepted too, that's how they were written
+/// before accounts could have letters.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AccountId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = AccountId;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an account like \"3000\" or \"A-4000\"")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<AccountId, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<AccountId, E> {
+                Ok(AccountId(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Ord for AccountId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let mut a = self.0.chars().peekable();
+        let mut b = other.0.chars().peekable();
+
+        loop {
+            match (a.peek().copied(), b.peek().copied()) {
+                (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                    let x = digit_run(&mut a);
+                    let y = digit_run(&mut b);
+                    let x = x.trim_start_matches('0');
+                    let y = y.trim_start_matches('0');
+                    let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                (Some(x), Some(y)) => {
+                    if x != y {
+                        return x.cmp(&y);
+                    }
+                    let _ = a.next();
+                    let _ = b.next();
+                }
+                (x, y) => {
+                    // the same apart from leading zeros, fall back to the text to stay
+                    // consistent with `Eq`
+                    return x.is_some().cmp(&y.is_some()).then_with(|| self.0.cmp(&other.0));
+                }
+            }
+        }
+    }
+}
+
+impl PartialOrd for AccountId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn digit_run(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        run.push(c);
+        let _ = chars.next();
+    }
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(s: &str) -> AccountId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_identifiers() {
+        assert_eq!(account("1920.01").as_str(), "1920.01");
+        assert_eq!(account("A-4000").to_string(), "A-4000");
+        assert_eq!(account("0150"), account("0150"));
+        assert_ne!(account("0150"), account("150"));
+        assert!("".parse::<AccountId>().is_err());
+        assert!("A-".parse::<AccountId>().is_err());
+        assert!("-4000".parse::<AccountId>().is_err());
+        assert!("1920..01".parse::<AccountId>().is_err());
+        assert!("30 00".parse::<AccountId>().is_err());
+    }
+
+    #[test]
+    fn orders_digits_by_value() {
+        let mut accounts: Vec<AccountId> = ["3000", "999", "150", "1920.10", "A-4000", "1920.2", "0150", "30000", "1920"]
+            .iter()
+            .map(|s| account(s))
+            .collect();
+        accounts.sort();
+
+        let sorted: Vec<&str> = accounts.iter().map(|a| a.as_str()).collect();
+        assert_eq!(sorted, vec!["0150", "150", "999", "1920", "1920.2", "1920.10", "3000", "30000", "A-4000"]);
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// An exact decimal amount.
+///
+/// Amounts are stored as a whole number of millionths so adding and subtracting them never loses
+/// precision the way floats do. Six decimals is more than any ledger we know of uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(i128);
+
+impl Amount {
+    /// The number of decimals an amount is stored with.
+    pub const SCALE: u32 = 6;
+    pub const ZERO: Amount = Amount(0);
+
+    const ONE: i128 = 10i128.pow(Amount::SCALE);
+
+    /// An amount without decimals.
+    pub fn from_int(value: i64) -> Self {
+        Amount(value as i128 * Amount::ONE)
+    }
+
+    /// An amount given in hundredths, so `Amount::from_cents(12345)` is `123.45`.
+    pub fn from_cents(cents: i64) -> Self {
+        Amount(cents as i128 * (Amount::ONE / 100))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(&self) -> Self {
+        Amount(self.0.abs())
+    }
+
+    /// Rounds to the given number of decimals, halves are rounded away from zero. An amount too
+    /// close to the largest or smallest amount to be rounded away from zero is rounded toward it.
+    pub fn round(&self, decimals: u32) -> Self {
+        if decimals >= Amount::SCALE {
+            return *self;
+        }
+
+        let unit = 10i128.pow(Amount::SCALE - decimals);
+        let rest = self.0 % unit;
+        let value = self.0 - rest;
+        if rest.abs() * 2 >= unit {
+            if let Some(away) = value.checked_add(unit * self.0.signum()) {
+                return Amount(away);
+            }
+        }
+
+        Amount(value)
+    }
+
+    /// Returns `None` if the sum doesn't fit in an amount.
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    /// Returns `None` if the difference doesn't fit in an amount.
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    /// Returns `None` if the negated amount doesn't fit in an amount.
+    pub fn checked_neg(self) -> Option<Amount> {
+        self.0.checked_neg().map(Amount)
+    }
+
+    /// Multiplies the amounts, rounded to `SCALE` decimals with halves away from zero. Returns
+    /// `None` if the product doesn't fit in an amount.
+    pub fn checked_mul(self, rhs: Amount) -> Option<Amount> {
+        let product = self.0.checked_mul(rhs.0)?;
+        divide_rounded(product, Amount::ONE).map(Amount)
+    }
+
+    /// Divides the amounts, rounded to `SCALE` decimals with halves away from zero. Returns `None`
+    /// when dividing by zero or if the quotient doesn't fit in an amount.
+    pub fn checked_div(self, rhs: Amount) -> Option<Amount> {
+        let dividend = self.0.checked_mul(Amount::ONE)?;
+        divide_rounded(dividend, rhs.0).map(Amount)
+    }
+
+    /// Formats the amount rounded to `decimals` decimals, with `thousands` between each group of
+    /// three digits (if given) and `decimal_point` before the decimals.
+    pub fn format(&self, decimals: u32, thousands: Option<char>, decimal_point: char) -> String {
+        let decimals = decimals.min(Amount::SCALE);
+        let rounded = self.round(decimals).0.unsigned_abs();
+        let whole = (rounded / Amount::ONE as u128).to_string();
+        let fraction = rounded % Amount::ONE as u128;
+
+        let mut text = String::new();
+        if self.round(decimals).0 < 0 {
+            text.push('-');
+        }
+
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                if let Some(sep) = thousands {
+                    text.push(sep);
+                }
+            }
+            text.push(c);
+        }
+
+        if decimals > 0 {
+            let fraction = format!("{:0width$}", fraction, width = Amount::SCALE as usize);
+            text.push(decimal_point);
+            text.push_str(&fraction[..decimals as usize]);
+        }
+
+        text
+    }
+}
+
+fn divide_rounded(dividend: i128, divisor: i128) -> Option<i128> {
+    let quotient = dividend.checked_div(divisor)?;
+    let rest = dividend.checked_rem(divisor)?.unsigned_abs();
+    if rest >= divisor.unsigned_abs() - rest {
+        Some(quotient + dividend.signum() * divisor.signum())
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Prints the amount with all its significant decimals, but at least two.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut decimals = Amount::SCALE;
+        while decimals > 2 && self.0 % 10i128.pow(Amount::SCALE - decimals + 1) == 0 {
+            decimals -= 1;
+        }
+
+        f.write_str(&self.format(decimals, None, '.'))
+    }
+}
+
+/// The error returned when parsing an `Amount` fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAmountError(String);
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid amount \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseAmountError {}
+
+/// Parses amounts like `1234`, `-1234.5` or `1234,50`. Either `.` or `,` can be used as the
+/// decimal point but there can be no thousands separators.
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseAmountError(s.to_string());
+
+        let text = s.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+
+        let (whole, fraction) = match text.find(['.', ',']) {
+            Some(i) => (&text[..i], &text[i + 1..]),
+            None => (text, ""),
+        };
+
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+            return Err(err());
+        }
+
+        if fraction.len() > Amount::SCALE as usize {
+            return Err(err());
+        }
+
+        let whole: i128 = whole.parse().map_err(|_| err())?;
+        let fraction: i128 = if fraction.is_empty() {
+            0
+        } else {
+            let padded = format!("{:0<width$}", fraction, width = Amount::SCALE as usize);
+            padded.parse().map_err(|_| err())?
+        };
+
+        let value = whole
+            .checked_mul(Amount::ONE)
+            .and_then(|whole| whole.checked_add(fraction))
+            .ok_or_else(err)?;
+
+        Ok(Amount(if negative { -value } else { value }))
+    }
+}
+
+/// Amounts are written as strings like `"1234.50"` so they don't lose precision, whole numbers are
+/// accepted too.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Amount {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Amount {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an amount like \"1234.50\"")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Amount, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Amount, E> {
+                Ok(Amount::from_int(value))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Amount, E> {
+                Ok(Amount(value as i128 * Amount::ONE))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Panics if the sum doesn't fit in an amount, use `checked_add` for amounts that can be that
+/// large, like the balances in a file.
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        self.checked_add(rhs).expect("attempt to add amounts with overflow")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        *self = *self + rhs;
+    }
+}
+
+/// Panics if the difference doesn't fit in an amount, use `checked_sub` for amounts that can be
+/// that large.
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        self.checked_sub(rhs).expect("attempt to subtract amounts with overflow")
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Amount) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        self.checked_neg().expect("attempt to negate an amount with overflow")
+    }
+}
+
+/// Panics if the sum doesn't fit in an amount like `Add` does.
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, |acc, amount| acc + *amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints() {
+        let amount: Amount = "-1234,5".parse().unwrap();
+        assert_eq!(amount, -Amount::from_cents(123450));
+        assert_eq!(amount.to_string(), "-1234.50");
+        assert_eq!("0.123456".parse::<Amount>().unwrap().to_string(), "0.123456");
+        assert!("1.2.3".parse::<Amount>().is_err());
+        assert!("12a".parse::<Amount>().is_err());
+        assert!("".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn adds_without_losing_precision() {
+        let tenth: Amount = "0.1".parse().unwrap();
+        let sum: Amount = (0..10).map(|_| tenth).sum();
+        assert_eq!(sum, Amount::from_int(1));
+    }
+
+    #[test]
+    fn multiplies_and_divides() {
+        let amount = |s: &str| s.parse::<Amount>().unwrap();
+        assert_eq!(amount("1234.5").checked_mul(amount("0.25")), Some(amount("308.625")));
+        assert_eq!(amount("-10").checked_div(amount("3")), Some(amount("-3.333333")));
+        assert_eq!(amount("2").checked_div(amount("3")), Some(amount("0.666667")));
+        assert_eq!(amount("1").checked_div(Amount::ZERO), None);
+        let huge = Amount::from_int(i64::MAX);
+        assert_eq!(huge.checked_mul(huge), None);
+
+        let max = Amount(i128::MAX);
+        assert_eq!(max.checked_add(Amount::from_cents(1)), None);
+        assert_eq!((-max).checked_sub(Amount::from_int(1)), None);
+        assert_eq!(Amount(i128::MIN).checked_neg(), None);
+    }
+
+    #[test]
+    fn formats_with_separators() {
+        let amount: Amount = "-1234567.895".parse().unwrap();
+        assert_eq!(amount.format(2, Some(' '), ','), "-1 234 567,90");
+        assert_eq!(amount.format(0, Some(','), '.'), "-1,234,568");
+        assert_eq!(Amount::from_cents(-4).format(1, None, '.'), "0.0");
+    }
+
+    #[test]
+    fn rounds_the_largest_amounts_toward_zero() {
+        let max = Amount(i128::MAX);
+        assert_eq!(max.round(2), Amount(i128::MAX - 5727));
+        assert_eq!(max.format(2, None, '.'), "170141183460469231731687303715884.10");
+        assert_eq!(Amount(i128::MIN).format(2, Some(' '), '.'), "-170 141 183 460 469 231 731 687 303 715 884.10");
+        assert_eq!(max.to_string(), "170141183460469231731687303715884.105727");
+        let balance: Amount = "170141183460469231731687303715884.105727".parse().unwrap();
+        assert_eq!(balance.format(0, None, '.'), "170141183460469231731687303715884");
+    }
+}
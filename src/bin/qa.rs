@@ -0,0 +1,349 @@
+//! Command line tool for checking, formatting and rendering report definitions.
+//!
+//! ```text
+//! qa check <definition.qa>...
+//! qa fmt [--check] <definition.qa>...
+//! qa dump <definition.qa>
+//! qa render [options] <definition.qa> <balances.csv>
+//! ```
+//!
+//! Every command takes `--alphanumeric-accounts` to allow account codes like `1920.01` and
+//! `A-4000` in the definitions. Included files are read relative to the file including them,
+//! `fmt` formats each file on its own.
+
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+use qa_parser::eval;
+use qa_parser::render::Align;
+use qa_parser::{
+    evaluate, evaluate_period, format_document, render_with, validate_document, AccountSyntax, CsvError, FileLoader, Parser,
+    RenderOptions, Severity, Span,
+};
+
+const USAGE: &str = "\
+Usage:
+    qa check <definition.qa>...
+        Parses and validates the definitions. Exits with 1 if there are any errors.
+
+    qa fmt [--check] <definition.qa>...
+        Formats the definitions in place. With --check nothing is written, the files that
+        aren't formatted are listed and qa exits with 1 if there are any.
+
+    qa dump <definition.qa>
+        Prints the syntax tree as JSON.
+
+    qa render [options] <definition.qa> <balances.csv>
+        Prints the report with the balances in the CSV file (account in the first column and
+        the balance in the second).
+
+        --width <n>            Width of the lines (default 60)
+        --amount-width <n>     Width of the amount column (default 15)
+        --decimals <n>         Number of decimals (default 2)
+        --thousands <char>     Thousands separator, use \"\" for none (default ,)
+        --decimal-point <char> Decimal point (default .)
+        --indent <n>           Indentation per nesting level (default 2)
+        --left                 Left align the amounts
+        --locale <tag>         Write the titles translated to this locale, like en or nb-NO.
+                               Give it more than once to fall back to the next one
+        --period               The CSV file has the opening balance in the second column and
+                               the closing balance in the third. Result reports show the
+                               change in the period and opening reports the opening balances
+
+Options for all commands:
+    --alphanumeric-accounts    Allow account codes like 1920.01 and A-4000 in the definitions,
+                               not just account numbers
+";
+
+/// Exit code for errors in the input files.
+const FAILURE: i32 = 1;
+/// Exit code for wrong usage.
+const USAGE_ERROR: i32 = 2;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let code = match run(&args) {
+        Ok(code) => code,
+        Err(CliError::Usage(msg)) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            USAGE_ERROR
+        }
+        Err(CliError::Failed(msg)) => {
+            eprintln!("error: {}", msg);
+            FAILURE
+        }
+    };
+
+    process::exit(code);
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+fn run(args: &[String]) -> Result<i32, CliError> {
+    let syntax = if args.iter().any(|arg| arg == "--alphanumeric-accounts") {
+        AccountSyntax::Alphanumeric
+    } else {
+        AccountSyntax::Digits
+    };
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--alphanumeric-accounts").cloned().collect();
+
+    let (command, args) = match args.split_first() {
+        Some(split) => split,
+        None => return Err(CliError::Usage("missing command".to_string())),
+    };
+
+    match command.as_str() {
+        "check" => check(args, syntax),
+        "fmt" => fmt(args, syntax),
+        "dump" => dump(args, syntax),
+        "render" => render(args, syntax),
+        "help" | "--help" | "-h" => {
+            output(USAGE);
+            Ok(0)
+        }
+        _ => Err(CliError::Usage(format!("unknown command `{}`", command))),
+    }
+}
+
+fn check(args: &[String], syntax: AccountSyntax) -> Result<i32, CliError> {
+    let files = files(args, 1, None)?;
+    let mut code = 0;
+
+    for file in files {
+        let source = read(file)?;
+        let (document, errors) = Parser::new(&source)
+            .account_syntax(syntax)
+            .file(file)
+            .loader(FileLoader::new())
+            .parse_document_recovering();
+
+        // the errors name the file they're in
+        for e in &errors {
+            output(&e.to_string());
+            code = FAILURE;
+        }
+
+        // the syntax tree is incomplete if there are syntax errors so we don't validate it
+        if !errors.is_empty() {
+            continue;
+        }
+
+        for mut diagnostic in validate_document(&document) {
+            // the problem is in an included file
+            let report = match &diagnostic.file {
+                Some(included) => diagnostic.report(&read(included)?),
+                None => {
+                    diagnostic.file = Some(file.to_string());
+                    diagnostic.report(&source)
+                }
+            };
+            output(&report);
+            if diagnostic.severity == Severity::Error {
+                code = FAILURE;
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+fn fmt(args: &[String], syntax: AccountSyntax) -> Result<i32, CliError> {
+    let check_only = args.iter().any(|arg| arg == "--check");
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--check").cloned().collect();
+    let files = files(&args, 1, None)?;
+    let mut code = 0;
+
+    for file in files {
+        let source = read(file)?;
+        // the included files are read, but only the include lines are written
+        let document = Parser::new(&source)
+            .account_syntax(syntax)
+            .file(file)
+            .loader(FileLoader::new())
+            .parse_document()
+            .map_err(|e| CliError::Failed(e.to_string()))?;
+        let formatted = format_document(&document);
+
+        if formatted == source {
+            continue;
+        }
+
+        if check_only {
+            output(&format!("{} is not formatted\n", file));
+            code = FAILURE;
+        } else {
+            fs::write(file, formatted)
+                .map_err(|e| CliError::Failed(format!("could not write {}: {}", file, e)))?;
+        }
+    }
+
+    Ok(code)
+}
+
+fn dump(args: &[String], syntax: AccountSyntax) -> Result<i32, CliError> {
+    let files = files(args, 1, Some(1))?;
+    let source = read(files[0])?;
+    let spans = parse(files[0], &source, syntax)?;
+
+    let json = serde_json::to_string_pretty(&spans)
+        .map_err(|e| CliError::Failed(format!("could not serialize {}: {}", files[0], e)))?;
+    output(&format!("{}\n", json));
+    Ok(0)
+}
+
+fn render(args: &[String], syntax: AccountSyntax) -> Result<i32, CliError> {
+    let mut options = RenderOptions::default();
+    let mut files = vec![];
+    let mut period = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| CliError::Usage(format!("missing value for {}", arg)))
+        };
+
+        match arg.as_str() {
+            "--width" => options.width = number(arg, value()?)?,
+            "--amount-width" => options.amount_width = number(arg, value()?)?,
+            "--decimals" => options.decimals = number(arg, value()?)?,
+            "--indent" => options.indent = number(arg, value()?)?,
+            "--thousands" => options.thousands_separator = value()?.chars().next(),
+            "--decimal-point" => {
+                options.decimal_point = value()?
+                    .chars()
+                    .next()
+                    .ok_or_else(|| CliError::Usage("--decimal-point can't be empty".to_string()))?
+            }
+            "--left" => options.amount_align = Align::Left,
+            "--period" => period = true,
+            "--locale" => options.locales.push(value()?.clone()),
+            _ if arg.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option {}", arg)))
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    let files = self::files(&files, 2, Some(2))?;
+    let source = read(files[0])?;
+    let spans = parse(files[0], &source, syntax)?;
+
+    let balances = read(files[1])?;
+    let failed = |e: CsvError| CliError::Failed(format!("{}: {}", files[1], e));
+    let report = if period {
+        evaluate_period(&spans, &eval::parse_period(&balances).map_err(failed)?)
+    } else {
+        evaluate(&spans, &eval::parse_balances(&balances).map_err(failed)?)
+    };
+    let report = report.map_err(|e| CliError::Failed(format!("{}: {}", files[0], e)))?;
+    output(&render_with(&report, &options));
+    Ok(0)
+}
+
+/// Checks that there are between `min` and `max` file arguments.
+fn files(args: &[String], min: usize, max: Option<usize>) -> Result<Vec<&str>, CliError> {
+    if let Some(option) = args.iter().find(|arg| arg.starts_with("--")) {
+        return Err(CliError::Usage(format!("unknown option {}", option)));
+    }
+
+    if args.len() < min {
+        return Err(CliError::Usage("missing file".to_string()));
+    }
+
+    if let Some(max) = max {
+        if args.len() > max {
+            return Err(CliError::Usage("too many files".to_string()));
+        }
+    }
+
+    Ok(args.iter().map(|arg| arg.as_str()).collect())
+}
+
+fn read(file: &str) -> Result<String, CliError> {
+    fs::read_to_string(file).map_err(|e| CliError::Failed(format!("could not read {}: {}", file, e)))
+}
+
+/// Parses the file with the files it includes.
+fn parse(file: &str, source: &str, syntax: AccountSyntax) -> Result<Vec<Span>, CliError> {
+    Parser::new(source)
+        .account_syntax(syntax)
+        .file(file)
+        .loader(FileLoader::new())
+        .parse()
+        .map_err(|e| CliError::Failed(e.to_string()))
+}
+
+/// Writes to stdout. Unlike `print!` this doesn't panic when the output is piped to a program
+/// that exits early, like `qa dump report.qa | head`.
+fn output(text: &str) {
+    let _ = io::stdout().lock().write_all(text.as_bytes());
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::Usage(format!("{} expects a number, got `{}`", option, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reports_usage_errors() {
+        assert!(matches!(run(&args(&[])), Err(CliError::Usage(_))));
+        assert!(matches!(run(&args(&["build"])), Err(CliError::Usage(_))));
+        assert!(matches!(run(&args(&["dump", "a.qa", "b.qa"])), Err(CliError::Usage(_))));
+        assert!(matches!(run(&args(&["render", "--width", "wide", "a.qa", "b.csv"])), Err(CliError::Usage(_))));
+        assert!(matches!(run(&args(&["check", "--strict", "a.qa"])), Err(CliError::Usage(_))));
+    }
+
+    /// A directory of its own for each test, so tests running at the same time don't share files.
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("qa-cli-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn checks_files() {
+        let dir = test_dir("checks_files");
+        let good = dir.join("good.qa");
+        let bad = dir.join("bad.qa");
+        fs::write(&good, "(\n    3000..3999 => Sales\n) => Sum\n").unwrap();
+        fs::write(&bad, "(\n    3999..3000 => Sales\n) => Sum\n").unwrap();
+
+        let path = |p: &std::path::Path| p.to_string_lossy().to_string();
+        assert_eq!(run(&[String::from("check"), path(&good)]).unwrap(), 0);
+        assert_eq!(run(&[String::from("check"), path(&bad)]).unwrap(), FAILURE);
+        assert_eq!(run(&[String::from("fmt"), String::from("--check"), path(&good)]).unwrap(), 0);
+
+        let codes = dir.join("codes.qa");
+        fs::write(&codes, "(\n    A-3000..A-3999 => Sales\n) => Sum\n").unwrap();
+        assert_eq!(run(&[String::from("check"), path(&codes)]).unwrap(), FAILURE);
+        assert_eq!(run(&args(&["check", "--alphanumeric-accounts", &path(&codes)])).unwrap(), 0);
+
+        // the included files are found next to the file including them
+        let including = dir.join("including.qa");
+        fs::write(&including, "include \"good.qa\"\n\nTotal = Sum\n").unwrap();
+        assert_eq!(run(&[String::from("check"), path(&including)]).unwrap(), 0);
+        assert_eq!(run(&[String::from("fmt"), String::from("--check"), path(&including)]).unwrap(), 0);
+        fs::write(&including, "include \"bad.qa\"\n").unwrap();
+        assert_eq!(run(&[String::from("check"), path(&including)]).unwrap(), FAILURE);
+        fs::write(&including, "include \"missing.qa\"\n").unwrap();
+        assert_eq!(run(&[String::from("check"), path(&including)]).unwrap(), FAILURE);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+//! Checks a report definition against a chart of accounts.
+//!
+//! Given the accounts that exist in a company's ledger `coverage` tells you which accounts are not
+//! captured by any range (they won't show up in the report), which accounts are captured by more
+//! than one range (they'll be counted twice) and which ranges don't match any account at all.
+//!
+//! ```rust, ignore
+//! let spans = Parser::new(input).parse()?;
+//! let chart = parse_chart_of_accounts(&std::fs::read_to_string("accounts.csv")?)?;
+//! let report = coverage(&spans, &account_numbers(&chart));
+//! ```
+
+use crate::csv::{self, CsvError};
+use crate::{AccountId, Range, Span};
+
+/// An account in a chart of accounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub number: AccountId,
+    pub name: Option<String>,
+}
+
+/// An account that is captured by more than one range.
+#[derive(Debug)]
+pub struct MultipleMapping<'a> {
+    pub account: AccountId,
+    pub ranges: Vec<&'a Range>,
+}
+
+/// The result of checking a report definition against a chart of accounts.
+#[derive(Debug)]
+pub struct Coverage<'a> {
+    /// Accounts not captured by any range.
+    pub unmapped: Vec<AccountId>,
+    /// Accounts captured by more than one range, together with the ranges that capture them.
+    pub multiply_mapped: Vec<MultipleMapping<'a>>,
+    /// Ranges that don't capture any of the accounts.
+    pub unmatched_ranges: Vec<&'a Range>,
+}
+
+impl Coverage<'_> {
+    /// Returns true if every account is captured by exactly one range and every range captures
+    /// at least one account.
+    pub fn is_complete(&self) -> bool {
+        self.unmapped.is_empty() && self.multiply_mapped.is_empty() && self.unmatched_ranges.is_empty()
+    }
+}
+
+/// Checks which of the accounts are captured by the ranges in the spans. The accounts don't need
+/// to be sorted and duplicates are ignored.
+pub fn coverage<'a>(spans: &'a [Span], accounts: &[AccountId]) -> Coverage<'a> {
+    let mut ranges = vec![];
+    for span in spans {
+        collect_ranges(span, &mut ranges);
+    }
+
+    let mut accounts = accounts.to_vec();
+    accounts.sort();
+    accounts.dedup();
+
+    let mut unmapped = vec![];
+    let mut multiply_mapped = vec![];
+    let mut matched = vec![false; ranges.len()];
+
+    for account in accounts {
+        let mut claimed_by = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            if range.contains(&account) {
+                matched[i] = true;
+                claimed_by.push(*range);
+            }
+        }
+
+        match claimed_by.len() {
+            0 => unmapped.push(account),
+            1 => (),
+            _ => multiply_mapped.push(MultipleMapping {
+                account,
+                ranges: claimed_by,
+            }),
+        }
+    }
+
+    let unmatched_ranges = ranges
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(range, _)| *range)
+        .collect();
+
+    Coverage {
+        unmapped,
+        multiply_mapped,
+        unmatched_ranges,
+    }
+}
+
+fn collect_ranges<'a>(span: &'a Span, out: &mut Vec<&'a Range>) {
+    out.extend(span.ranges.iter());
+    for subspan in &span.subspans {
+        collect_ranges(subspan, out);
+    }
+}
+
+/// Reads a chart of accounts from CSV. The account is the first column and an optional account
+/// name the second, any other columns are ignored. A header line is skipped if the first column
+/// of the first line doesn't have any digits.
+pub fn parse_chart_of_accounts(input: &str) -> Result<Vec<Account>, CsvError> {
+    let mut accounts = vec![];
+
+    for (i, (line, fields)) in csv::records(input)?.into_iter().enumerate() {
+        if i == 0 && csv::is_header(&fields[0]) {
+            continue;
+        }
+
+        let number = fields[0].parse().map_err(|e| CsvError {
+            line,
+            message: format!("{}", e),
+        })?;
+
+        let name = fields.get(1).filter(|name| !name.is_empty()).cloned();
+        accounts.push(Account { number, name });
+    }
+
+    Ok(accounts)
+}
+
+/// The account numbers of the accounts, convenient for passing on to `coverage`.
+pub fn account_numbers(accounts: &[Account]) -> Vec<AccountId> {
+    accounts.iter().map(|account| account.number.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    #[test]
+    fn finds_unmapped_and_multiply_mapped_accounts() {
+        let test = "
+        Sales (
+            3010..3010 => Webshop
+            3010..3999 => Other sales
+        ) => Sum sales
+
+        (
+            4000..4999 => Material
+            5000..5999 => Labor
+        ) => Sum costs
+        ";
+
+        let spans = Parser::new(test).parse().unwrap();
+        let accounts: Vec<AccountId> = vec![3000.into(), 3010.into(), 3020.into(), 4000.into(), 4000.into(), 1920.into()];
+        let coverage = coverage(&spans, &accounts);
+
+        assert_eq!(coverage.unmapped, vec![1920.into(), 3000.into()]);
+        assert_eq!(coverage.multiply_mapped.len(), 1);
+        assert_eq!(coverage.multiply_mapped[0].account, 3010.into());
+        let titles: Vec<_> = coverage.multiply_mapped[0].ranges.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, vec!["Webshop", "Other sales"]);
+        assert_eq!(coverage.unmatched_ranges.len(), 1);
+        assert_eq!(coverage.unmatched_ranges[0].title, "Labor");
+        assert!(!coverage.is_complete());
+    }
+
+    #[test]
+    fn honours_exclusions() {
+        let test = "
+        Sales (
+            3010 => Webshop
+            3000..3999, 8100, !3010 => Other sales
+        ) => Sum sales
+        ";
+
+        let spans = Parser::new(test).parse().unwrap();
+        let coverage = coverage(&spans, &[3000.into(), 3010.into(), 8100.into()]);
+        assert!(coverage.is_complete());
+    }
+
+    #[test]
+    fn reads_chart_of_accounts() {
+        let input = "Account;Name\n1920;Bank\n3000;Sales\n3010\n0150.A\n";
+        let accounts = parse_chart_of_accounts(input).unwrap();
+        let numbers: Vec<String> = account_numbers(&accounts).iter().map(|a| a.to_string()).collect();
+        assert_eq!(numbers, vec!["1920", "3000", "3010", "0150.A"]);
+        assert_eq!(accounts[0].name.as_deref(), Some("Bank"));
+        assert_eq!(accounts[2].name, None);
+
+        let err = parse_chart_of_accounts("1920,Bank\nBank 1,1930\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,120 @@
+//! A minimal CSV reader. It's just enough to read the charts of accounts and balances exported
+//! from accounting systems so we don't need a dependency for it.
+//!
+//! Fields are separated by `;` if there is one outside of quotes on the first line and by `,`
+//! otherwise, so the amounts can have decimal commas in a file separated by `;`. They can be quoted
+//! with `"` and a quote inside a quoted field is written as `""`. Empty lines are skipped.
+
+use std::fmt;
+
+/// An error reading a CSV file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvError {
+    /// The 1-based line the error occurred on.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line: {}, ERROR: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Splits the input into records. Returns the line number of each record together with its
+/// fields.
+pub(crate) fn records(input: &str) -> Result<Vec<(usize, Vec<String>)>, CsvError> {
+    let first_line = input.lines().next().unwrap_or("");
+    // a `,` on the first line can be a decimal comma, a `;` can't
+    let mut quoted = false;
+    let has_semicolon = first_line.chars().any(|c| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ';' && !quoted
+    });
+    let separator = if has_semicolon { ';' } else { ',' };
+
+    let mut records = vec![];
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = fields(line, separator).ok_or_else(|| CsvError {
+            line: i + 1,
+            message: "Unterminated quote".to_string(),
+        })?;
+        records.push((i + 1, fields));
+    }
+
+    Ok(records)
+}
+
+/// Returns true if the first field of the first line is a header like `Account` or `Konto`
+/// rather than an account. Accounts can have letters so we go by if there are any digits.
+pub(crate) fn is_header(field: &str) -> bool {
+    !field.chars().any(|c| c.is_ascii_digit())
+}
+
+fn fields(line: &str, separator: char) -> Option<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    let _ = chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            c if c == separator && !quoted => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+
+    fields.push(field.trim().to_string());
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_fields() {
+        let input = "account;name\n3000;\"Sales; \"\"domestic\"\"\"\n\n3010;Webshop\n";
+        let records = records(input).unwrap();
+        assert_eq!(records, vec![
+            (1, vec!["account".to_string(), "name".to_string()]),
+            (2, vec!["3000".to_string(), "Sales; \"domestic\"".to_string()]),
+            (4, vec!["3010".to_string(), "Webshop".to_string()]),
+        ]);
+
+        assert_eq!(self::records("1,\"open").unwrap_err().line, 1);
+
+        // decimal commas without a header, and a `;` in quotes
+        let records = self::records("3000;1234,50\n3010;1,5\n").unwrap();
+        assert_eq!(records[0].1, vec!["3000".to_string(), "1234,50".to_string()]);
+        let records = self::records("3000,\"Sales; domestic\"\n").unwrap();
+        assert_eq!(records[0].1, vec!["3000".to_string(), "Sales; domestic".to_string()]);
+    }
+}
@@ -0,0 +1,243 @@
+//! The syntax tree of a whole input, with the lines written outside of the blocks.
+//!
+//! `Parser::parse` gives you the spans, which is what you evaluate and render. The `let`,
+//! `include` and `template` lines and the `report balance { .. }` sections aren't spans, so
+//! `Parser::parse_document` gives you a `Document` with every line in the order it's written, for
+//! the tools that need all of them like the formatter and the validator.
+
+use crate::range_set::{collect, resolve_span};
+use crate::{Formula, Location, RangeSet, ReportKind, Span, Template};
+
+/// Everything written outside of a block, in the order it's written.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Document {
+    pub items: Vec<Item>,
+    /// Comment lines after the last item.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub trailing_comments: Vec<String>,
+}
+
+/// A line or block written outside of a block.
+///
+/// With the `serde` feature this is serialized as the item with a `"kind"` like `"span"` or
+/// `"range_set"` added to it.
+// most items are spans, so boxing them would only add an allocation to each of them
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Item {
+    /// A block. Its `formulas` are empty, the formulas after it are items of their own.
+    Span(Span),
+    /// A line like `Gross profit = Sum sales - Sum material`. It belongs to the last span before
+    /// it.
+    Formula(Formula),
+    /// A line like `let bank = 1900..1999, !1950`.
+    RangeSet(RangeSet),
+    /// A line like `include "equity.qa"`, with the items of the file.
+    Include(Include),
+    /// A `template department(title, base)` line with its block.
+    Template(Template),
+    /// A `report balance { .. }` section with the items in it.
+    Report(Report),
+}
+
+/// An `include "equity.qa"` line and the file it includes.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Include {
+    /// The path as it's written.
+    pub path: String,
+    /// The name the loader read the file by, see `Loader::resolve`.
+    pub file: String,
+    /// Where in the source the `include` line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the `include` line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// A comment after the path on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+    /// The items of the file. It's empty in the `include` of a span, the spans of the file are
+    /// subspans of the block then.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub document: Document,
+}
+
+/// A `report balance { .. }` section. The spans in it have its kind as their `report`.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// The kind after `report`. With the `serde` feature it's written as `"report"`, like the
+    /// `report` of a span.
+    #[cfg_attr(feature = "serde", serde(rename = "report"))]
+    pub kind: ReportKind,
+    /// The items between the `{` and the `}`. A report can't have reports of its own.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub items: Vec<Item>,
+    /// Where in the source the `report balance {` line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the `report` line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// A comment after the `{` on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+    /// Comment lines right before the `}`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub end_comments: Vec<String>,
+    /// A comment after the `}` on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub end_comment: Option<String>,
+}
+
+impl Document {
+    /// The spans with the formulas after them in `formulas`, the same as `Parser::parse` gives
+    /// you. The spans of an included file take the place of the include and the spans of a report
+    /// the place of the report, and the trailing comments are the trailing comments of the last
+    /// span. The comments of the `report` and `}` lines are only in the `Report`.
+    pub fn spans(&self) -> Vec<Span> {
+        self.clone().into_spans()
+    }
+
+    /// Like `spans`, without cloning them.
+    pub fn into_spans(self) -> Vec<Span> {
+        let mut spans = vec![];
+        push_spans(self, &mut spans);
+        spans
+    }
+
+    /// The range sets in the order they're written, with the ones in the reports and the included
+    /// files.
+    pub fn range_sets(&self) -> Vec<&RangeSet> {
+        let mut sets = vec![];
+        for_each_item(&self.items, &mut |item| {
+            if let Item::RangeSet(set) = item {
+                sets.push(set);
+            }
+        });
+        sets
+    }
+
+    /// The templates in the order they're written, with the ones in the reports and the included
+    /// files.
+    pub fn templates(&self) -> Vec<&Template> {
+        let mut templates = vec![];
+        for_each_item(&self.items, &mut |item| {
+            if let Item::Template(template) = item {
+                templates.push(template);
+            }
+        });
+        templates
+    }
+
+    /// Puts the accounts of the range sets in the account lists of the ranges that use them, see
+    /// `resolve_range_sets`. `Parser::parse_document` does this for you.
+    pub fn resolve_range_sets(&mut self) {
+        let sets = collect(self.range_sets());
+        for span in all_spans_mut(&mut self.items) {
+            resolve_span(span, &sets);
+        }
+    }
+}
+
+/// Two includes are equal if they include the same items the same way, where they're defined in
+/// the source and the name the file was read by is not compared.
+impl PartialEq for Include {
+    fn eq(&self, other: &Include) -> bool {
+        self.path == other.path
+            && self.comments == other.comments
+            && self.comment == other.comment
+            && self.document == other.document
+    }
+}
+
+impl Eq for Include {}
+
+/// Two reports are equal if they have the same kind, items and comments, where they're defined in
+/// the source is not compared.
+impl PartialEq for Report {
+    fn eq(&self, other: &Report) -> bool {
+        self.kind == other.kind
+            && self.items == other.items
+            && self.comments == other.comments
+            && self.comment == other.comment
+            && self.end_comments == other.end_comments
+            && self.end_comment == other.end_comment
+    }
+}
+
+impl Eq for Report {}
+
+/// Adds the spans of the document to `spans` with every formula in the last span before it. A
+/// formula before the first span is dropped, the parser doesn't accept one.
+fn push_spans(document: Document, spans: &mut Vec<Span>) {
+    for item in document.items {
+        match item {
+            Item::Span(span) => spans.push(span),
+            Item::Formula(formula) => {
+                if let Some(last) = spans.last_mut() {
+                    last.formulas.push(formula);
+                }
+            }
+            Item::RangeSet(_) | Item::Template(_) => (),
+            Item::Include(include) => push_spans(include.document, spans),
+            Item::Report(report) => push_spans(Document { items: report.items, trailing_comments: vec![] }, spans),
+        }
+    }
+    if let Some(last) = spans.last_mut() {
+        last.trailing_comments.extend(document.trailing_comments);
+    }
+}
+
+/// Calls `f` with every item, and the items in the reports and the included files after the
+/// report or include.
+fn for_each_item<'a>(items: &'a [Item], f: &mut impl FnMut(&'a Item)) {
+    for item in items {
+        f(item);
+        match item {
+            Item::Include(include) => for_each_item(&include.document.items, f),
+            Item::Report(report) => for_each_item(&report.items, f),
+            _ => (),
+        }
+    }
+}
+
+/// The spans of the items and of the reports and files in them, not their subspans.
+pub(crate) fn all_spans_mut(items: &mut [Item]) -> Vec<&mut Span> {
+    let mut spans = vec![];
+    for item in items {
+        match item {
+            Item::Span(span) => spans.push(span),
+            Item::Include(include) => spans.extend(all_spans_mut(&mut include.document.items)),
+            Item::Report(report) => spans.extend(all_spans_mut(&mut report.items)),
+            _ => (),
+        }
+    }
+    spans
+}
+
+/// The spans of the items and of the reports and files in them, not their subspans.
+pub(crate) fn all_spans(items: &[Item]) -> Vec<&Span> {
+    let mut spans = vec![];
+    for_each_item(items, &mut |item| {
+        if let Item::Span(span) = item {
+            spans.push(span);
+        }
+    });
+    spans
+}
+
+/// True if there is a span in the items or in the reports and files in them.
+pub(crate) fn has_span(items: &[Item]) -> bool {
+    !all_spans(items).is_empty()
+}
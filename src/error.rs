@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::Position;
+
+/// The different kinds of syntax errors the parser can report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The `..` between the two account numbers of a range is missing or malformed.
+    InvalidRangeSyntax,
+    /// The end of a range, or an entry in the account list, is not an account number.
+    InvalidRange,
+    /// Found something else than `=>` after a range, or `=` after `)` not followed by `>`.
+    ExpectedArrow,
+    /// Found `)` but it's not followed by `=>`.
+    ExpectedArrowAfterParen,
+    /// Found `=` after a range but it's not followed by `>`.
+    InvalidSyntaxAfterEq,
+    /// The input ended in the middle of a construct.
+    UnexpectedEof,
+    /// Found something that doesn't belong where it is, like a `}` without a report.
+    UnexpectedSyntax,
+    /// Expected the `)` that ends a block.
+    ExpectedCloseParen,
+    /// Found something outside of a block.
+    ExpectedBlock,
+    /// The account isn't a valid account identifier, like `A-` or `4000-`.
+    InvalidAccount,
+    /// The blocks are nested more than `MAX_NESTING` levels.
+    NestingTooDeep,
+    /// Something follows the wildcards of an account pattern, like `3*0` or `30xx*`.
+    InvalidPattern,
+    /// Every account in the account list of a range is excluded, like `!3010 => Sales`.
+    OnlyExclusions,
+    /// A formula line starts with `=`, like `= Sum sales - Sum material`.
+    MissingFormulaTitle,
+    /// A formula comes before the first block, so there's no span to keep it with.
+    FormulaBeforeBlock,
+    /// Expected a number, a reference or `(` in a formula.
+    ExpectedOperand,
+    /// A reference in brackets like `[Sum sales` isn't closed on the same line.
+    UnclosedReference,
+    /// A formula has more than `MAX_NESTING` operators and parentheses.
+    FormulaTooLong,
+    /// An attribute isn't written like `#[key]` or `#[key = value, key = "text"]`.
+    InvalidAttribute,
+    /// The same key is used twice in the attributes of a line.
+    DuplicateAttribute,
+    /// Attributes are followed by `)`, `}` or the end of the input instead of the line they belong
+    /// to.
+    DanglingAttributes,
+    /// A string in quotes isn't closed on the same line.
+    UnclosedString,
+    /// A `\` in a string in quotes isn't followed by `"`, `\`, `n` or `t`.
+    InvalidEscape,
+    /// A title is translated to the same locale twice, like `Salg | en: Sales | en: Revenue`.
+    DuplicateTranslation,
+    /// The loader couldn't read the file of an `include "path.qa"` line. The name of the file and
+    /// why it couldn't be read are in `ParseError::note`.
+    IncludeFailed,
+    /// Found an `include "path.qa"` line but the parser has no loader to read the file with.
+    NoLoader,
+    /// A file included in a block has no blocks.
+    EmptyInclude,
+    /// A file includes itself, directly or through other files.
+    CircularInclude,
+    /// The name of a range set, template or parameter is missing, like after `let` or `@`.
+    ExpectedName,
+    /// A template has two parameters with the same name, like `template department(base, base)`.
+    DuplicateParameter,
+    /// A `{parameter}` in the body of a template isn't one of its parameters.
+    UnknownParameter,
+    /// A template is used with more or fewer arguments than it has parameters.
+    ArgumentCount,
+    /// An argument of a `use` line has a line break, or the template expands to something else
+    /// than one block with the arguments. An error in the block is reported as it is, see
+    /// `ParseError::note`.
+    InvalidArguments,
+    /// A template uses itself, directly or through other templates.
+    CircularTemplate,
+    /// A `use` line uses a template that isn't defined before it.
+    UndefinedTemplate,
+    /// The kind after `report` isn't `balance`, `result` or `opening`.
+    InvalidReportKind,
+    /// A `report balance {` is inside another report.
+    NestedReport,
+    /// A `report balance {` isn't closed with `}`.
+    UnclosedReport,
+}
+
+impl ErrorKind {
+    /// A short human readable description of the error.
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRangeSyntax => "Invalid range syntax",
+            ErrorKind::InvalidRange => "Invalid range",
+            ErrorKind::ExpectedArrow => "Expected =>",
+            ErrorKind::ExpectedArrowAfterParen => "Expected => after )",
+            ErrorKind::InvalidSyntaxAfterEq => "Invalid syntax after =",
+            ErrorKind::UnexpectedEof => "Unexpected EOF",
+            ErrorKind::UnexpectedSyntax => "Unexpected syntax",
+            ErrorKind::ExpectedCloseParen => "Expected )",
+            ErrorKind::ExpectedBlock => "Expected a block",
+            ErrorKind::InvalidAccount => "Invalid account",
+            ErrorKind::NestingTooDeep => "Blocks are nested too deep",
+            ErrorKind::InvalidPattern => "Invalid account pattern",
+            ErrorKind::OnlyExclusions => "Range only has excluded accounts",
+            ErrorKind::MissingFormulaTitle => "Expected a title before the = of the formula",
+            ErrorKind::FormulaBeforeBlock => "Expected a block before the formula",
+            ErrorKind::ExpectedOperand => "Expected a number or a reference",
+            ErrorKind::UnclosedReference => "Expected ] after the reference",
+            ErrorKind::FormulaTooLong => "Formula is too long",
+            ErrorKind::InvalidAttribute => "Invalid attribute",
+            ErrorKind::DuplicateAttribute => "Duplicate attribute",
+            ErrorKind::DanglingAttributes => "Expected a range, block or formula after the attributes",
+            ErrorKind::UnclosedString => "Expected \" at the end of the string",
+            ErrorKind::InvalidEscape => "Invalid escape in string",
+            ErrorKind::DuplicateTranslation => "Duplicate translation",
+            ErrorKind::IncludeFailed => "Could not read the included file",
+            ErrorKind::NoLoader => "There is no loader to read the included file",
+            ErrorKind::EmptyInclude => "The included file has no blocks",
+            ErrorKind::CircularInclude => "The file includes itself",
+            ErrorKind::ExpectedName => "Expected a name",
+            ErrorKind::DuplicateParameter => "Duplicate parameter",
+            ErrorKind::UnknownParameter => "Not a parameter of the template",
+            ErrorKind::ArgumentCount => "Wrong number of arguments for the template",
+            ErrorKind::InvalidArguments => "The template can't be used with these arguments",
+            ErrorKind::CircularTemplate => "The template uses itself",
+            ErrorKind::UndefinedTemplate => "The template isn't defined before it's used",
+            ErrorKind::InvalidReportKind => "Expected balance, result or opening after report",
+            ErrorKind::NestedReport => "A report can't be inside another report",
+            ErrorKind::UnclosedReport => "Expected } at the end of the report",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// A syntax error with the position it occurred at and the source line it occurred on.
+///
+/// The `Display` implementation renders the error the same way the parser always has:
+///
+/// ```text
+/// line: 5, pos: 22
+///                 6020.6100 => Office Supplies
+/// ---------------------^
+///
+/// ERROR: Invalid range syntax
+/// ```
+///
+/// The first line starts with `file: equity.qa, ` if the error is in a file with a name, and the
+/// `note` is written on a line of its own after the message like
+/// `NOTE: in template department with argument base = "x"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    pub position: Position,
+    /// The full line of source text the error occurred on (without the line break).
+    pub source_line: String,
+    /// The file the error is in, if it's an included file or the parser was given the name of
+    /// its input with `Parser::file`.
+    pub file: Option<String>,
+    /// Where the error is from if it isn't on the source line, like the template and argument of
+    /// a `use` line whose block doesn't parse, or why an included file couldn't be read.
+    pub note: Option<String>,
+}
+
+impl ParseError {
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.position.column
+    }
+
+    pub fn offset(&self) -> usize {
+        self.position.offset
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_report(f, self.file.as_deref(), self.position, &self.source_line, "ERROR", &self.kind)?;
+        match &self.note {
+            Some(note) => writeln!(f, "NOTE: {}", note),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes a message pointing at a position on a source line, like:
+///
+/// ```text
+/// line: 5, pos: 22
+///                 6020.6100 => Office Supplies
+/// ---------------------^
+///
+/// ERROR: Invalid range syntax
+/// ```
+pub(crate) fn write_report<W: fmt::Write>(
+    f: &mut W,
+    file: Option<&str>,
+    position: Position,
+    source_line: &str,
+    level: &str,
+    message: &dyn fmt::Display,
+) -> fmt::Result {
+    let mut indicator = String::new();
+    for (i, _) in source_line.chars().enumerate() {
+        let pos = i + 1;
+        if pos < position.column {
+            indicator.push('-');
+        } else if pos == position.column {
+            indicator.push('^');
+        }
+    }
+
+    f.write_char('\n')?;
+    if let Some(file) = file {
+        write!(f, "file: {}, ", file)?;
+    }
+    write!(
+        f,
+        "line: {}, pos: {}\n{}\n{}\n\n{}: {}\n",
+        position.line, position.column, source_line, indicator, level, message
+    )
+}
+
+impl std::error::Error for ParseError {}
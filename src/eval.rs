@@ -0,0 +1,569 @@
+//! Evaluates a report definition against account balances.
+//!
+//! The amount of a `Range` is the sum of the balances of every account in its account list, both
+//! ends of a range included, so `3010..3010` is the single account 3010. Accounts are compared the
+//! way `AccountId` orders them, so `1920.01` is in `1920..1930`. Excluded accounts like
+//! `!3010` are left out and an account listed twice on the same line is only counted once. The
+//! total of a span is the sum of its ranges and the totals of its subspans. Accounts without a
+//! balance count as zero, and a reversed range like `4000..3000` doesn't capture any accounts
+//! (`validate` reports those).
+//!
+//! A range or span written with a `-` in front, like `-3000..3999 => Sales` or `-Sales (`, is
+//! shown with the opposite sign. For a span that goes for every line in it and its total, so a
+//! range with a `-` inside a span with a `-` is shown with the sign of the balances again. The
+//! totals are always the sum of the amounts as they're shown.
+//!
+//! A `Formula` like `Gross profit = Sum sales - Sum material` is computed from the totals of the
+//! spans with those sum labels and the results of other formulas. If a name is used for more than
+//! one line, a sum label counts before a formula title and the first one in the source before the
+//! rest. A formula has no amount if it refers to a line that doesn't exist, refers to itself,
+//! divides by zero or the result is too large for an `Amount`.
+//!
+//! `evaluate` uses the same balances for every span. `evaluate_period` takes the opening and
+//! closing balances of a period and picks them by the `report` section a span is in: the movement
+//! in the period (closing minus opening) for `report result`, the opening balances for `report
+//! opening` and the closing balances for `report balance` and spans outside a report.
+//!
+//! The amount of a range or the total of a span that's too large for an `Amount` is an
+//! `EvalError`, and so is a balance in a file that adds up to one.
+//!
+//! ```rust, ignore
+//! let spans = Parser::new(input).parse()?;
+//! let balances = eval::parse_balances(&std::fs::read_to_string("balances.csv")?)?;
+//! for span in evaluate(&spans, &balances)? {
+//!     println!("{:?}: {}", span.span.name, span.total);
+//! }
+//!
+//! let period = eval::parse_period(&std::fs::read_to_string("period.csv")?)?;
+//! let report = evaluate_period(&spans, &period)?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::csv::{self, CsvError};
+use crate::{AccountId, Amount, Expr, Formula, Location, Operator, Range, ReportKind, Span};
+
+/// The balance of each account.
+pub type Balances = BTreeMap<AccountId, Amount>;
+
+/// The balance of each account at the start and the end of a period.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Period {
+    pub opening: Balances,
+    pub closing: Balances,
+}
+
+impl Period {
+    /// The change of each account in the period, the closing balance minus the opening balance.
+    pub fn movement(&self) -> Result<Balances, EvalError> {
+        let mut movement = self.closing.clone();
+        for (account, amount) in &self.opening {
+            let change = movement.entry(account.clone()).or_default();
+            *change = change.checked_sub(*amount).ok_or_else(|| EvalError {
+                message: format!("The movement of account {} is too large", account),
+                location: None,
+                file: None,
+            })?;
+        }
+        Ok(movement)
+    }
+}
+
+/// The error returned when an amount in the report is too large for an `Amount`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    pub message: String,
+    /// Where the range or span whose amount is too large is, if it's the amount of a line.
+    pub location: Option<Location>,
+    /// The file the line is in if it's in a span included from another file, see `Span::file`.
+    pub file: Option<String>,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {}", self.message)?;
+        if let Some(location) = &self.location {
+            f.write_str(" (")?;
+            if let Some(file) = &self.file {
+                write!(f, "file: {}, ", file)?;
+            }
+            write!(f, "line: {}, pos: {})", location.start.line, location.start.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A `Range` with its computed amount.
+#[derive(Debug, Clone)]
+pub struct EvaluatedRange<'a> {
+    pub range: &'a Range,
+    pub amount: Amount,
+}
+
+/// A `Span` with the computed amounts of its ranges and subspans. `total` is the sub total of a
+/// nested span and the sum total of a top level span.
+#[derive(Debug, Clone)]
+pub struct EvaluatedSpan<'a> {
+    pub span: &'a Span,
+    pub ranges: Vec<EvaluatedRange<'a>>,
+    pub subspans: Vec<EvaluatedSpan<'a>>,
+    pub total: Amount,
+    pub formulas: Vec<EvaluatedFormula<'a>>,
+}
+
+/// A `Formula` with its computed amount, `None` if it can't be computed.
+#[derive(Debug, Clone)]
+pub struct EvaluatedFormula<'a> {
+    pub formula: &'a Formula,
+    pub amount: Option<Amount>,
+}
+
+/// Computes the amount of every range, the total of every span and the result of every formula.
+/// Returns an error if the amount of a range or the total of a span is too large for an `Amount`.
+pub fn evaluate<'a>(spans: &'a [Span], balances: &Balances) -> Result<Vec<EvaluatedSpan<'a>>, EvalError> {
+    evaluate_with(spans, |_| balances)
+}
+
+/// Like `evaluate`, but the balances of each span depend on the kind of `report` it's in: the
+/// movement in the period for `result`, the opening balances for `opening` and the closing
+/// balances for `balance` and spans that aren't in a report. Formulas can refer to the totals of
+/// every kind.
+pub fn evaluate_period<'a>(spans: &'a [Span], period: &Period) -> Result<Vec<EvaluatedSpan<'a>>, EvalError> {
+    let movement = period.movement()?;
+    evaluate_with(spans, |report| match report {
+        Some(ReportKind::Result) => &movement,
+        Some(ReportKind::Opening) => &period.opening,
+        Some(ReportKind::Balance) | None => &period.closing,
+    })
+}
+
+fn evaluate_with<'a, 'b>(
+    spans: &'a [Span],
+    balances: impl Fn(Option<ReportKind>) -> &'b Balances,
+) -> Result<Vec<EvaluatedSpan<'a>>, EvalError> {
+    let mut report: Vec<EvaluatedSpan> = spans
+        .iter()
+        .map(|span| evaluate_span(span, balances(span.report), false))
+        .collect::<Result<_, _>>()?;
+
+    let mut lines = Lines::default();
+    for span in &report {
+        lines.add_totals(span);
+    }
+    for span in spans {
+        lines.add_formulas(span);
+    }
+
+    for span in &mut report {
+        lines.evaluate_formulas(span);
+    }
+
+    Ok(report)
+}
+
+/// `reversed` is true if the amounts of the parent span are shown with the opposite sign.
+fn evaluate_span<'a>(span: &'a Span, balances: &Balances, reversed: bool) -> Result<EvaluatedSpan<'a>, EvalError> {
+    let reversed = reversed != span.reverse_sign;
+    let too_large = |what: String, location: Location| EvalError {
+        message: format!("The {} is too large", what),
+        location: Some(location),
+        file: span.file.clone(),
+    };
+
+    let ranges: Vec<EvaluatedRange> = span
+        .ranges
+        .iter()
+        .map(|range| {
+            let mut amount = range_amount(range, balances);
+            if reversed != range.reverse_sign {
+                amount = amount.and_then(Amount::checked_neg);
+            }
+            let amount = amount.ok_or_else(|| too_large(format!("amount of {}", range.title), range.location))?;
+            Ok(EvaluatedRange { range, amount })
+        })
+        .collect::<Result<_, _>>()?;
+
+    let subspans: Vec<EvaluatedSpan> = span
+        .subspans
+        .iter()
+        .map(|subspan| evaluate_span(subspan, balances, reversed))
+        .collect::<Result<_, _>>()?;
+
+    let total = ranges
+        .iter()
+        .map(|r| r.amount)
+        .chain(subspans.iter().map(|s| s.total))
+        .try_fold(Amount::ZERO, Amount::checked_add);
+    let label = span.sum_type.name().filter(|label| !label.is_empty()).unwrap_or("the span");
+    let total = total.ok_or_else(|| too_large(format!("total of {}", label), span.sum_location))?;
+
+    Ok(EvaluatedSpan {
+        span,
+        ranges,
+        subspans,
+        total,
+        formulas: vec![],
+    })
+}
+
+/// The lines formulas can refer to by name.
+#[derive(Default)]
+struct Lines<'a> {
+    totals: HashMap<&'a str, Amount>,
+    formulas: HashMap<&'a str, &'a Formula>,
+    /// The results of the formulas we have computed so far.
+    results: HashMap<&'a str, Option<Amount>>,
+    /// The formulas we're computing right now, if we get to one of them again it refers to
+    /// itself.
+    computing: HashSet<&'a str>,
+}
+
+impl<'a> Lines<'a> {
+    /// Adds the totals of the span and its subspans in the order the sum labels are written.
+    fn add_totals(&mut self, span: &EvaluatedSpan<'a>) {
+        for subspan in &span.subspans {
+            self.add_totals(subspan);
+        }
+
+        if let Some(label) = span.span.sum_type.name().filter(|label| !label.is_empty()) {
+            self.totals.entry(label).or_insert(span.total);
+        }
+    }
+
+    fn add_formulas(&mut self, span: &'a Span) {
+        for subspan in &span.subspans {
+            self.add_formulas(subspan);
+        }
+
+        for formula in &span.formulas {
+            self.formulas.entry(&formula.title).or_insert(formula);
+        }
+    }
+
+    fn evaluate_formulas(&mut self, span: &mut EvaluatedSpan<'a>) {
+        for subspan in &mut span.subspans {
+            self.evaluate_formulas(subspan);
+        }
+
+        span.formulas = span
+            .span
+            .formulas
+            .iter()
+            .map(|formula| EvaluatedFormula {
+                formula,
+                amount: self.expr(&formula.expr),
+            })
+            .collect();
+    }
+
+    fn reference(&mut self, name: &'a str) -> Option<Amount> {
+        if let Some(total) = self.totals.get(name) {
+            return Some(*total);
+        }
+
+        if let Some(result) = self.results.get(name) {
+            return *result;
+        }
+
+        let formula = *self.formulas.get(name)?;
+        if !self.computing.insert(name) {
+            return None;
+        }
+        let result = self.expr(&formula.expr);
+        self.computing.remove(name);
+
+        self.results.insert(name, result);
+        result
+    }
+
+    fn expr(&mut self, expr: &'a Expr) -> Option<Amount> {
+        match expr {
+            Expr::Number { value } => Some(*value),
+            Expr::Reference { name } => self.reference(name),
+            Expr::Negate { operand } => self.expr(operand).and_then(Amount::checked_neg),
+            Expr::Binary { operator, left, right } => {
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide => left.checked_div(right),
+                }
+            }
+        }
+    }
+}
+
+/// The sum of the balances of the accounts in the range, `None` if it's too large for an amount.
+fn range_amount(range: &Range, balances: &Balances) -> Option<Amount> {
+    balances
+        .iter()
+        .filter(|(account, _)| range.contains(account))
+        .try_fold(Amount::ZERO, |sum, (_, amount)| sum.checked_add(*amount))
+}
+
+/// Reads account balances from CSV. The account is the first column and the balance the second,
+/// any other columns are ignored. A header line is skipped if the first column of the first line
+/// doesn't have any digits. If an account is listed more than once the balances are added up, and
+/// it's an error if they add up to more than an `Amount` can hold.
+pub fn parse_balances(input: &str) -> Result<Balances, CsvError> {
+    let mut balances = Balances::new();
+
+    for (i, (line, fields)) in csv::records(input)?.into_iter().enumerate() {
+        if i == 0 && csv::is_header(&fields[0]) {
+            continue;
+        }
+
+        let account: AccountId = field(line, &fields, 0)?;
+        let amount: Amount = field(line, &fields, 1)?;
+        add_balance(&mut balances, account, amount, line)?;
+    }
+
+    Ok(balances)
+}
+
+/// Reads the balances of a period from CSV, the account in the first column, the opening balance
+/// in the second and the closing balance in the third. Header lines and accounts listed more than
+/// once are handled like in `parse_balances`.
+pub fn parse_period(input: &str) -> Result<Period, CsvError> {
+    let mut period = Period::default();
+
+    for (i, (line, fields)) in csv::records(input)?.into_iter().enumerate() {
+        if i == 0 && csv::is_header(&fields[0]) {
+            continue;
+        }
+
+        let account: AccountId = field(line, &fields, 0)?;
+        let opening: Amount = field(line, &fields, 1)?;
+        let closing: Amount = field(line, &fields, 2)?;
+        add_balance(&mut period.opening, account.clone(), opening, line)?;
+        add_balance(&mut period.closing, account, closing, line)?;
+    }
+
+    Ok(period)
+}
+
+/// Adds the amount to the balance of the account read on `line`.
+fn add_balance(balances: &mut Balances, account: AccountId, amount: Amount, line: usize) -> Result<(), CsvError> {
+    let balance = balances.get(&account).copied().unwrap_or_default();
+    let balance = balance.checked_add(amount).ok_or_else(|| CsvError {
+        line,
+        message: format!("The balance of account {} is too large", account),
+    })?;
+    balances.insert(account, balance);
+    Ok(())
+}
+
+/// Parses column `i` of a CSV line, a missing column is parsed as an empty field.
+fn field<T>(line: usize, fields: &[String], i: usize) -> Result<T, CsvError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let field = fields.get(i).map(|field| field.as_str()).unwrap_or("");
+    field.parse().map_err(|e| CsvError {
+        line,
+        message: format!("{}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccountSyntax, Parser};
+
+    #[test]
+    fn evaluates_ranges_and_totals() {
+        let test = "
+        Other costs (
+            6000..6010 => Leasing
+            (
+                6020..6099 => Office supplies
+                6100..6200 => Consumables
+            ) => Sum miscellaneous costs
+        ) => Sum other costs
+        (
+            7000..6000 => Reversed
+        ) => Sum nothing
+        ";
+
+        let balances = parse_balances("account,balance\n6000,100.10\n6010,0.05\n6011,1000\n6099,20\n6100,30.5\n6200,1\n6200,1\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let amount = |s: &str| s.parse::<Amount>().unwrap();
+        assert_eq!(report[0].ranges[0].amount, amount("100.15"));
+        assert_eq!(report[0].subspans[0].ranges[0].amount, amount("20"));
+        assert_eq!(report[0].subspans[0].ranges[1].amount, amount("32.5"));
+        assert_eq!(report[0].subspans[0].total, amount("52.5"));
+        assert_eq!(report[0].total, amount("152.65"));
+        assert_eq!(report[1].total, Amount::ZERO);
+    }
+
+    #[test]
+    fn honours_exclusions() {
+        let test = "(\n    3000..3999, 3500, 8100, !3010, !3400..3499 => Other sales\n) => Sum";
+        let balances = parse_balances("3000,1\n3010,10\n3450,100\n3500,1000\n8100,10000\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+        assert_eq!(report[0].total, Amount::from_int(11001));
+    }
+
+    #[test]
+    fn matches_account_patterns() {
+        let test = "(\n    30xx => Sales\n    4* => Material\n) => Sum";
+        let balances = parse_balances("300,1\n3000,10\n3099,100\n30000,1000\n4,1\n41,10\n4999,100\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+        assert_eq!(report[0].ranges[0].amount, Amount::from_int(110));
+        assert_eq!(report[0].ranges[1].amount, Amount::from_int(111));
+    }
+
+    #[test]
+    fn evaluates_account_identifiers() {
+        let test = "(\n    1920..1930 => Bank\n    0150, A-4000 => Other\n) => Sum";
+        let balances = parse_balances("Konto;Saldo\n1920.01;1\n1920.5;10\n1931;100\n0150;1000\n150;10000\nA-4000;100000\n").unwrap();
+        let spans = Parser::new(test).account_syntax(AccountSyntax::Alphanumeric).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+        assert_eq!(report[0].ranges[0].amount, Amount::from_int(11));
+        assert_eq!(report[0].ranges[1].amount, Amount::from_int(101000));
+
+        let balances = parse_balances("3000;1234,50\n3010;1,5\n").unwrap();
+        assert_eq!(balances.values().next(), Some(&"1234.50".parse::<Amount>().unwrap()));
+    }
+
+    #[test]
+    fn reverses_signs() {
+        let test = "
+        -Sales (
+            3000 => Webshop
+            -3100 => Returns
+            (
+                3200 => Other
+            ) => Sum other
+        ) => Sum sales
+        (
+            -3000 => Webshop
+        ) => Sum
+        Gross profit = Sum sales * 1
+        ";
+
+        let balances = parse_balances("3000,-1000\n3100,50\n3200,-10\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        assert_eq!(report[0].ranges[0].amount, Amount::from_int(1000));
+        assert_eq!(report[0].ranges[1].amount, Amount::from_int(50));
+        assert_eq!(report[0].subspans[0].total, Amount::from_int(10));
+        assert_eq!(report[0].total, Amount::from_int(1060));
+        assert_eq!(report[1].total, Amount::from_int(1000));
+        assert_eq!(report[1].formulas[0].amount, Some(Amount::from_int(1060)));
+    }
+
+    #[test]
+    fn evaluates_formulas() {
+        let test = "
+        (
+            3000..3999 => Sales
+        ) => Sum sales
+
+        (
+            4000..4999 => Material
+        ) => Sum material
+
+        Gross profit = Sum sales - Sum material
+        Gross margin = Gross profit / Sum sales * 100
+        Operating result = Gross profit - [Sum other costs] + -(Gross profit - Gross profit) * 2
+
+        (
+            6000..6999 => Other costs
+        ) => Sum other costs
+
+        Broken = Sum sales / (Sum material - 250)
+        Cycle = Cycle + 1
+        Unknown = Sum salse
+        ";
+
+        let balances = parse_balances("3000,1000
+4000,250
+6000,500
+").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let amounts = |span: &EvaluatedSpan| -> Vec<Option<Amount>> { span.formulas.iter().map(|f| f.amount).collect() };
+        assert_eq!(amounts(&report[1]), vec![Some(Amount::from_int(750)), Some(Amount::from_int(75)), Some(Amount::from_int(250))]);
+        assert_eq!(amounts(&report[2]), vec![None, None, None]);
+    }
+
+    #[test]
+    fn evaluates_periods() {
+        let test = "
+        report balance {
+            (
+                1900..1999 => Bank
+            ) => Sum bank
+        }
+        report opening {
+            (
+                1900..1999 => Bank
+            ) => Sum opening bank
+        }
+        report result {
+            (
+                3000..3999 => Sales
+            ) => Sum sales
+        }
+        (
+            1900..1999 => Bank
+        ) => Sum closing bank
+
+        Change = Sum bank - Sum opening bank
+        ";
+
+        let period = parse_period("account,opening,closing\n1900,100,250\n3000,-1000,-1600\n3000,0,-10\n").unwrap();
+        assert_eq!(period.movement().unwrap().get(&"1900".parse().unwrap()), Some(&Amount::from_int(150)));
+
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate_period(&spans, &period).unwrap();
+        assert_eq!(report[0].total, Amount::from_int(250));
+        assert_eq!(report[1].total, Amount::from_int(100));
+        assert_eq!(report[2].total, Amount::from_int(-610));
+        assert_eq!(report[3].total, Amount::from_int(250));
+        assert_eq!(report[3].formulas[0].amount, Some(Amount::from_int(150)));
+
+        assert_eq!(parse_period("1900,100\n").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn reports_amounts_too_large() {
+        let huge = "100000000000000000000000000000000";
+        let err = parse_balances(&format!("3000,{}\n3010,1\n3000,{}\n", huge, huge)).unwrap_err();
+        assert_eq!(err.line, 3);
+
+        let balances = parse_balances(&format!("3000,{}\n3001,{}\n", huge, huge)).unwrap();
+        let spans = Parser::new("(\n    3000..3001 => Sales\n) => Sum").parse().unwrap();
+        let err = evaluate(&spans, &balances).unwrap_err();
+        assert_eq!(err.message, "The amount of Sales is too large");
+        assert_eq!(err.location.unwrap().start.line, 2);
+
+        let spans = Parser::new("(\n    3000 => Webshop\n    3001 => Other\n) => Sum sales").parse().unwrap();
+        let err = evaluate(&spans, &balances).unwrap_err();
+        assert_eq!(err.to_string(), "error: The total of Sum sales is too large (line: 4, pos: 1)");
+
+        let period = parse_period(&format!("3000,-{},{}\n", huge, huge)).unwrap();
+        assert!(evaluate_period(&[], &period).is_err());
+    }
+
+    #[test]
+    fn reports_invalid_balances() {
+        let err = parse_balances("3000,12.5\n3010,twelve\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = parse_balances("3000,12.5\n30 10,12\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,740 @@
+use std::fmt::{self, Write};
+
+use crate::locale::has_translation_start;
+use crate::{
+    is_bare_argument_char, is_bare_attribute_char, is_include, is_let, is_report, AccountPattern, AccountRange, Amount, Attributes,
+    Document, Expr, Formula, Include, Item, Operator, ParseError, Parser, Range, RangeSet, Report, ReportKind, Span,
+    Template, Translations,
+};
+
+/// The number of spaces each level of nesting is indented with.
+const INDENT: usize = 4;
+
+/// Writes the spans back out as canonical DSL text.
+///
+/// Nested blocks are indented four spaces, the `=>` arrows of the ranges in a block are aligned,
+/// top level spans are separated by an empty line and comments are written with `//`. The
+/// spans after each other with the same `report` kind are written in one `report balance { .. }`,
+/// indented one level. Parsing the output gives you back the same spans, so
+/// `parse(format(spans)) == spans`, as long as they don't use range sets or templates. The `let`
+/// and `template` lines aren't spans, use `format_document` to write them too.
+pub fn format(spans: &[Span]) -> String {
+    let mut out = String::new();
+    let mut report = None;
+
+    for (i, span) in spans.iter().enumerate() {
+        // writing to a String can't fail
+        let level = write_report_change(&mut out, &mut report, span.report, i > 0);
+        let _ = write_span(&mut out, span, level);
+
+        if !span.formulas.is_empty() {
+            out.push('\n');
+            for formula in &span.formulas {
+                let _ = write_formula(&mut out, formula, level);
+            }
+        }
+
+        let _ = write_trailing_comments(&mut out, &span.trailing_comments, level);
+    }
+
+    if report.is_some() {
+        out.push_str("}\n");
+    }
+
+    out
+}
+
+/// Writes the document back out as canonical DSL text the way `format` writes the spans, with
+/// the `let`, `include` and `template` lines where they are and every report as it's written. The
+/// `let` lines after each other and the formulas after each other aren't separated by an empty
+/// line. Parsing the output gives you back the same document, so
+/// `parse_document(format_document(document)) == document`.
+pub fn format_document(document: &Document) -> String {
+    let mut out = String::new();
+    // writing to a String can't fail
+    let _ = write_items(&mut out, &document.items, 0);
+
+    if !document.trailing_comments.is_empty() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let _ = write_comments(&mut out, &document.trailing_comments, 0);
+    }
+
+    out
+}
+
+/// Returns true if the input is already formatted the way `format_document` would write it.
+pub fn is_formatted(input: &str) -> Result<bool, ParseError> {
+    let document = Parser::new(input).parse_document()?;
+    Ok(format_document(&document) == input)
+}
+
+/// Closes and opens the `report balance {` lines for a line in the report `next`, with an empty
+/// line before the line if `separate`. Returns the level the line is indented to.
+fn write_report_change(out: &mut String, report: &mut Option<ReportKind>, next: Option<ReportKind>, separate: bool) -> usize {
+    if next != *report {
+        if report.is_some() {
+            out.push_str("}\n");
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        if let Some(kind) = next {
+            let _ = writeln!(out, "report {} {{", kind);
+        }
+        *report = next;
+    } else if separate {
+        out.push('\n');
+    }
+
+    if report.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Writes the items with an empty line between them.
+fn write_items<W: Write>(out: &mut W, items: &[Item], level: usize) -> fmt::Result {
+    let mut previous: Option<&Item> = None;
+
+    for item in items {
+        let separate = !matches!(
+            (previous, item),
+            (None, _) | (Some(Item::RangeSet(_)), Item::RangeSet(_)) | (Some(Item::Formula(_)), Item::Formula(_))
+        );
+        if separate {
+            out.write_char('\n')?;
+        }
+
+        match item {
+            Item::Span(span) => {
+                write_span(out, span, level)?;
+                write_trailing_comments(out, &span.trailing_comments, level)?;
+            }
+            Item::Formula(formula) => write_formula(out, formula, level)?,
+            Item::RangeSet(set) => write_range_set(out, set, level)?,
+            Item::Include(include) => write_include(out, include, level)?,
+            Item::Template(template) => write_template(out, template, level)?,
+            Item::Report(report) => write_report(out, report, level)?,
+        }
+        previous = Some(item);
+    }
+
+    Ok(())
+}
+
+/// report balance { .. }
+fn write_report<W: Write>(out: &mut W, report: &Report, level: usize) -> fmt::Result {
+    let indent = " ".repeat(level * INDENT);
+
+    write_comments(out, &report.comments, level)?;
+    write!(out, "{}report {} {{", indent, report.kind)?;
+    write_trailing_comment(out, &report.comment)?;
+    write_items(out, &report.items, level + 1)?;
+    if report.items.is_empty() {
+        write_comments(out, &report.end_comments, level + 1)?;
+    } else {
+        write_trailing_comments(out, &report.end_comments, level + 1)?;
+    }
+    write!(out, "{}}}", indent)?;
+    write_trailing_comment(out, &report.end_comment)
+}
+
+fn write_formula<W: Write>(out: &mut W, formula: &Formula, level: usize) -> fmt::Result {
+    write_comments(out, &formula.comments, level)?;
+    write_attributes(out, &formula.attributes, level)?;
+    writeln!(out, "{}{}", " ".repeat(level * INDENT), formula)
+}
+
+/// The comment lines after the last line of a span, after an empty line.
+fn write_trailing_comments<W: Write>(out: &mut W, comments: &[String], level: usize) -> fmt::Result {
+    if comments.is_empty() {
+        return Ok(());
+    }
+    out.write_char('\n')?;
+    write_comments(out, comments, level)
+}
+
+/// include "equity.qa"
+fn write_include<W: Write>(out: &mut W, include: &Include, level: usize) -> fmt::Result {
+    write_comments(out, &include.comments, level)?;
+    write!(out, "{}include {}", " ".repeat(level * INDENT), quoted(&include.path))?;
+    write_trailing_comment(out, &include.comment)
+}
+
+/// let bank = 1900..1999, !1950
+fn write_range_set<W: Write>(out: &mut W, set: &RangeSet, level: usize) -> fmt::Result {
+    write_comments(out, &set.comments, level)?;
+    write!(out, "{}let {} = {}", " ".repeat(level * INDENT), set.name, account_list(&set.accounts))?;
+    write_trailing_comment(out, &set.comment)
+}
+
+/// template department(title, base), with the block as it's written
+fn write_template<W: Write>(out: &mut W, template: &Template, level: usize) -> fmt::Result {
+    write_comments(out, &template.comments, level)?;
+    write!(out, "{}template {}({})", " ".repeat(level * INDENT), template.name, template.parameters.join(", "))?;
+    write_trailing_comment(out, &template.comment)?;
+    writeln!(out, "{}", template.body)
+}
+
+/// Writes the span the way `format` does.
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_span(f, self, 0)
+    }
+}
+
+/// Writes the range line without the comment lines before it.
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_range(f, self, 0)
+    }
+}
+
+/// Writes `from..to`, just `from` if it was written as a single account or the pattern like `30xx`,
+/// with a `!` in front of exclusions.
+impl fmt::Display for AccountRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.excluded {
+            f.write_char('!')?;
+        }
+
+        if let Some(pattern) = &self.pattern {
+            write!(f, "{}", pattern)
+        } else if self.single_account {
+            write!(f, "{}", self.from)
+        } else {
+            write!(f, "{}..{}", self.from, self.to)
+        }
+    }
+}
+
+impl fmt::Display for AccountPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountPattern::Digits { prefix, wildcards } => {
+                write!(f, "{}{}", prefix, "x".repeat(*wildcards as usize))
+            }
+            AccountPattern::Prefix { prefix } => write!(f, "{}*", prefix),
+        }
+    }
+}
+
+/// Writes the keyword after `report`, like `balance`.
+impl fmt::Display for ReportKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportKind::Balance => f.write_str("balance"),
+            ReportKind::Result => f.write_str("result"),
+            ReportKind::Opening => f.write_str("opening"),
+        }
+    }
+}
+
+/// Writes `title = expr` without the comment lines before it.
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&header(&self.title))?;
+        write_translations(f, &self.translations, header)?;
+        write!(f, " = {}", self.expr)?;
+        match &self.comment {
+            Some(comment) => write!(f, " {}", comment_line(comment)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the expression with spaces around the operators and only the parentheses it needs,
+/// like `(Sum sales - Sum material) / Sum sales`. A reference is written in brackets if it
+/// couldn't be read back without them, and in quotes if it can't be written in brackets either.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expr(f, self, 0)
+    }
+}
+
+/// Writes the expression in parentheses if it binds looser than `precedence`.
+fn write_expr<W: Write>(out: &mut W, expr: &Expr, precedence: u8) -> fmt::Result {
+    match expr {
+        Expr::Number { value } => {
+            // `100`, not `100.00`
+            let number = value.to_string();
+            out.write_str(number.trim_end_matches('0').trim_end_matches('.'))
+        }
+        Expr::Reference { name } if is_bare_reference(name) => out.write_str(name),
+        Expr::Reference { name } if !name.is_empty() && name.trim() == name && !name.contains([']', '\n']) => {
+            write!(out, "[{}]", name)
+        }
+        Expr::Reference { name } => out.write_str(&quoted(name)),
+        Expr::Negate { operand } => {
+            out.write_char('-')?;
+            write_expr(out, operand, 3)
+        }
+        Expr::Binary { operator, left, right } => {
+            let (symbol, own) = match operator {
+                Operator::Add => ('+', 1),
+                Operator::Subtract => ('-', 1),
+                Operator::Multiply => ('*', 2),
+                Operator::Divide => ('/', 2),
+            };
+
+            if own < precedence {
+                out.write_char('(')?;
+            }
+            // `a - (b - c)` needs the parentheses, `(a - b) - c` doesn't
+            write_expr(out, left, own)?;
+            write!(out, " {} ", symbol)?;
+            write_expr(out, right, own + 1)?;
+            if own < precedence {
+                out.write_char(')')?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A reference can be written without brackets if it doesn't have any operators, parentheses or
+/// comments in it, doesn't start with a quote and can't be read as a number.
+fn is_bare_reference(name: &str) -> bool {
+    !name.is_empty()
+        && name.trim() == name
+        && !name.starts_with('"')
+        && !name.contains(|c: char| "+-*/()[]#\n".contains(c))
+        && name.parse::<Amount>().is_err()
+}
+
+fn write_span<W: Write>(out: &mut W, span: &Span, level: usize) -> fmt::Result {
+    let indent = " ".repeat(level * INDENT);
+    let inner = " ".repeat((level + 1) * INDENT);
+
+    write_comments(out, &span.comments, level)?;
+
+    // use department(Sales, 30)
+    if let Some(template_use) = &span.template_use {
+        let arguments: Vec<String> = template_use
+            .arguments
+            .iter()
+            .map(|argument| match argument {
+                _ if !argument.is_empty() && argument.chars().all(is_bare_argument_char) => argument.clone(),
+                _ => quoted(argument),
+            })
+            .collect();
+        write!(out, "{}use {}({})", indent, template_use.name, arguments.join(", "))?;
+        return write_trailing_comment(out, &template_use.comment);
+    }
+
+    write_attributes(out, &span.attributes, level)?;
+
+    out.write_str(&indent)?;
+    if span.reverse_sign {
+        out.write_char('-')?;
+    }
+    let mut title = span.name.as_deref().map(header).unwrap_or_default();
+    write_translations(&mut title, &span.name_translations, header)?;
+    match title.trim_start() {
+        "" => out.write_char('(')?,
+        title => write!(out, "{} (", title)?,
+    }
+    write_trailing_comment(out, &span.header_comment)?;
+
+    let width = span.ranges.iter().map(|r| range_part(r).len()).max().unwrap_or(0);
+    for range in &span.ranges {
+        write_comments(out, &range.comments, level + 1)?;
+        write_attributes(out, &range.attributes, level + 1)?;
+        out.write_str(&inner)?;
+        write_range(out, range, width)?;
+        out.write_char('\n')?;
+    }
+
+    let mut previous: Option<&Include> = None;
+    for subspan in &span.subspans {
+        match &subspan.include {
+            // the spans of an included file are written as the line once
+            Some(include) if matches!(previous, Some(previous) if previous.location == include.location) => (),
+            Some(include) => write_include(out, include, level + 1)?,
+            None => write_span(out, subspan, level + 1)?,
+        }
+        previous = subspan.include.as_ref();
+    }
+
+    write_comments(out, &span.end_comments, level + 1)?;
+
+    match span.sum_type.name() {
+        Some(name) => {
+            write!(out, "{}) =>", indent)?;
+            if !name.is_empty() {
+                write!(out, " {}", label(name))?;
+            }
+            write_translations(out, span.sum_type.translations(), label)?;
+        }
+        None => write!(out, "{})", indent)?,
+    }
+    write_trailing_comment(out, &span.sum_comment)
+}
+
+/// Writes `accounts => title`, with the account list padded to `width`.
+fn write_range<W: Write>(out: &mut W, range: &Range, width: usize) -> fmt::Result {
+    write!(out, "{:<width$} =>", range_part(range), width = width)?;
+    if !range.title.is_empty() {
+        write!(out, " {}", label(&range.title))?;
+    }
+    write_translations(out, &range.translations, label)?;
+
+    match &range.comment {
+        Some(comment) => write!(out, " {}", comment_line(comment)),
+        None => Ok(()),
+    }
+}
+
+/// The account list of the range, like `3000..3999, 8100, !3010, @bank`, with a `-` in front if
+/// the sign is reversed. The accounts of a range set are written as the name of the set.
+pub(crate) fn range_part(range: &Range) -> String {
+    let mut parts = vec![];
+    let mut sets = range.sets.iter().peekable();
+    let mut next = 0;
+    loop {
+        while let Some(set) = sets.next_if(|set| set.start <= next) {
+            parts.push(format!("@{}", set.name));
+            next = next.max(set.start + set.len);
+        }
+
+        match range.accounts.get(next) {
+            Some(account) => parts.push(account.to_string()),
+            None => break,
+        }
+        next += 1;
+    }
+    parts.extend(sets.map(|set| format!("@{}", set.name)));
+
+    let sign = if range.reverse_sign { "-" } else { "" };
+    format!("{}{}", sign, parts.join(", "))
+}
+
+fn account_list(accounts: &[AccountRange]) -> String {
+    let accounts: Vec<String> = accounts.iter().map(|a| a.to_string()).collect();
+    accounts.join(", ")
+}
+
+fn write_comments<W: Write>(out: &mut W, comments: &[String], level: usize) -> fmt::Result {
+    let indent = " ".repeat(level * INDENT);
+    for comment in comments {
+        writeln!(out, "{}{}", indent, comment_line(comment))?;
+    }
+    Ok(())
+}
+
+/// Writes the attributes on one line like `#[hide_if_zero, note = 7]`. Flags with the value `true`
+/// are written without it.
+fn write_attributes<W: Write>(out: &mut W, attributes: &Attributes, level: usize) -> fmt::Result {
+    if attributes.is_empty() {
+        return Ok(());
+    }
+
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| match value.as_str() {
+            "true" => key.clone(),
+            _ if !value.is_empty() && value.chars().all(is_bare_attribute_char) => format!("{} = {}", key, value),
+            _ => format!("{} = {}", key, quoted(value)),
+        })
+        .collect();
+
+    writeln!(out, "{}#[{}]", " ".repeat(level * INDENT), attributes.join(", "))
+}
+
+/// Writes the translations of a title like ` | en: Sales | de: Umsatz`, with the titles quoted the
+/// same way as the title they're a translation of.
+fn write_translations<W: Write>(out: &mut W, translations: &Translations, quote: fn(&str) -> String) -> fmt::Result {
+    for (locale, title) in translations {
+        write!(out, " | {}:", locale)?;
+        let title = quote(title);
+        if !title.is_empty() {
+            write!(out, " {}", title)?;
+        }
+    }
+    Ok(())
+}
+
+/// Range titles and sum labels are the rest of the line, so they're written in quotes if they
+/// start with a quote, have spaces around them or have a comment, line break or translation in
+/// them.
+fn label(title: &str) -> String {
+    let plain = title.trim() == title
+        && !title.starts_with('"')
+        && !title.contains(['#', '\n'])
+        && !title.contains("//")
+        && !has_translation_start(title);
+    if plain {
+        title.to_string()
+    } else {
+        quoted(title)
+    }
+}
+
+/// Block headers and formula titles end at the `(` or `=`, so they're also written in quotes if
+/// they're empty, have `(`, `)` or `=` in them or start like a range, include, `let` or `report`
+/// line or the `}` of a report.
+fn header(title: &str) -> String {
+    let plain = !title.is_empty()
+        && !title.contains(['(', ')', '='])
+        && !title.starts_with(|c: char| c == '-' || c == '!' || c == '@' || c == '}' || c.is_ascii_digit())
+        && !is_include(title.chars())
+        && !is_report(title.chars())
+        // the title of a formula is followed by ` =`
+        && !is_let(title.chars().chain(" =".chars()));
+    if plain {
+        label(title)
+    } else {
+        quoted(title)
+    }
+}
+
+/// The text in double quotes with `"`, `\`, line breaks and tabs escaped.
+fn quoted(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Ends the line, with the comment if there is one.
+fn write_trailing_comment<W: Write>(out: &mut W, comment: &Option<String>) -> fmt::Result {
+    match comment {
+        Some(comment) => writeln!(out, " {}", comment_line(comment)),
+        None => out.write_char('\n'),
+    }
+}
+
+fn comment_line(comment: &str) -> String {
+    if comment.is_empty() {
+        "//".to_string()
+    } else {
+        format!("// {}", comment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryLoader;
+
+    #[test]
+    fn formats_canonically() {
+        let test = "
+        # Sales (all of them)
+          Sales (  // header
+            3010..3010 =>   Webshop # the new one
+            // moved 3050 here
+            3020..4000 => Other sales
+                (
+                6020..6100 => Office supplies
+            ) =>
+            #
+        ) => Sum sales
+
+        (
+          4000..5000 => Material
+        )
+        // the end
+        ";
+
+        let expected = "\
+// Sales (all of them)
+Sales ( // header
+    3010..3010 => Webshop // the new one
+    // moved 3050 here
+    3020..4000 => Other sales
+    (
+        6020..6100 => Office supplies
+    ) =>
+    //
+) => Sum sales
+
+(
+    4000..5000 => Material
+)
+
+// the end
+";
+
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, expected);
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+        assert!(is_formatted(&formatted).unwrap());
+        assert!(!is_formatted(test).unwrap());
+    }
+
+    #[test]
+    fn aligns_arrows() {
+        let test = "(\n3..4 => A\n3000..4000 => B\n) => Sum";
+        let spans = Parser::new(test).parse().unwrap();
+        assert_eq!(spans[0].to_string(), "(\n    3..4       => A\n    3000..4000 => B\n) => Sum\n");
+        assert_eq!(spans[0].ranges[1].to_string(), "3000..4000 => B");
+    }
+
+    #[test]
+    fn writes_account_lists() {
+        let test = "(\n3000..3999,8100 ,  !3010,!3500..3599 => Other sales\n) => Sum";
+        let spans = Parser::new(test).parse().unwrap();
+        assert_eq!(spans[0].ranges[0].to_string(), "3000..3999, 8100, !3010, !3500..3599 => Other sales");
+    }
+
+    #[test]
+    fn writes_account_patterns() {
+        let test = "(\n30XX, 8* , !301x => Sales\n) => Sum";
+        let spans = Parser::new(test).parse().unwrap();
+        assert_eq!(spans[0].ranges[0].to_string(), "30xx, 8*, !301x => Sales");
+        assert_eq!(Parser::new(&format(&spans)).parse().unwrap(), spans);
+    }
+
+    #[test]
+    fn writes_reversed_signs() {
+        let test = "- Sales (\n-3000..3999 => Sales\n  -(\n-8100 => Other\n  ) =>\n) => Sum sales";
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, "-Sales (\n    -3000..3999 => Sales\n    -(\n        -8100 => Other\n    ) =>\n) => Sum sales\n");
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+    }
+
+    #[test]
+    fn writes_attributes() {
+        let test = "#[page_break_before]\n#[ note=\"7\" ,bold ]\n(\n    #[hide_if_zero, label = \"Sales \\\"net\\\"\", drilldown = false]\n    3000 => Sales\n) => Sum\n#[x=1.5]\nA = Sum\n";
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, "#[bold, note = 7, page_break_before]\n(\n    #[drilldown = false, hide_if_zero, label = \"Sales \\\"net\\\"\"]\n    3000 => Sales\n) => Sum\n\n#[x = 1.5]\nA = Sum\n");
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+    }
+
+    #[test]
+    fn writes_formulas() {
+        let test = "(\n3000 => Sales\n) => Sum sales\n# gross\nGross profit=(Sum sales)-(Sum material-[Sum non-operating costs])  # comment\n\n\nMargin = (Gross profit)/(Sum sales * 1.50)*-(100)\n";
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, "(\n    3000 => Sales\n) => Sum sales\n\n// gross\nGross profit = Sum sales - (Sum material - [Sum non-operating costs]) // comment\nMargin = Gross profit / (Sum sales * 1.5) * -100\n");
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+        assert_eq!(spans[0].formulas[1].to_string(), "Margin = Gross profit / (Sum sales * 1.5) * -100");
+    }
+
+    #[test]
+    fn writes_quoted_titles() {
+        let test = r#"
+        "Depreciation (machinery)" (
+            6000 => "Leasing # 1"
+            6100 => "Say \"hi\" # now"
+            6200 => "Plain"
+            (6300 => x
+            ) => " Sum"
+        ) => "Sum // all"
+        "2024" (
+            3000 =>
+        ) => ""
+        "Result = x" = [Leasing # 1] - "[x]" * "Sum // all"
+        "#;
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, r#""Depreciation (machinery)" (
+    6000 => "Leasing # 1"
+    6100 => "Say \"hi\" # now"
+    6200 => Plain
+    (
+        6300 => x
+    ) => " Sum"
+) => "Sum // all"
+
+"2024" (
+    3000 =>
+) =>
+
+"Result = x" = [Leasing # 1] - "[x]" * [Sum // all]
+"#);
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+    }
+
+    #[test]
+    fn writes_includes() {
+        let test = "// shared\ninclude   \"equity.qa\"\n(\n  include \"costs \\\"old\\\".qa\" # old\n) => Sum\nTotal = Sum\n\"include \\\"x\\\"\" = 1\n";
+        let loader = || {
+            MemoryLoader::new()
+                .file("equity.qa", "(\n    2000 => Equity\n) => Sum equity\n")
+                .file("costs \"old\".qa", "(\n    4000 => Material\n) => Sum material\n(\n    5000 => Labor\n) => Sum labor\n")
+        };
+        let document = Parser::new(test).loader(loader()).parse_document().unwrap();
+        let formatted = format_document(&document);
+        assert_eq!(formatted, "// shared\ninclude \"equity.qa\"\n\n(\n    include \"costs \\\"old\\\".qa\" // old\n) => Sum\n\nTotal = Sum\n\"include \\\"x\\\"\" = 1\n");
+        assert_eq!(Parser::new(&formatted).loader(loader()).parse_document().unwrap(), document);
+
+        // the spans of a file included outside of a block are written as they are
+        let spans = document.into_spans();
+        assert!(format(&spans).starts_with("(\n    2000 => Equity\n) => Sum equity\n\n(\n    include \"costs"));
+    }
+
+    #[test]
+    fn writes_range_sets() {
+        let test = "// the bank\nlet bank=1900..1999 ,!1950 # no\nlet  cash = 19xx\n(\n  @bank,1800,@cash=>Bank\n  -@cash, @cash => Cash\n  @loans => Loans\n) => Sum\n\"let x\" = 1\n\"@x\" (\n) => Sum\n";
+        let document = Parser::new(test).parse_document().unwrap();
+        let formatted = format_document(&document);
+        assert_eq!(formatted, "// the bank\nlet bank = 1900..1999, !1950 // no\nlet cash = 19xx\n\n(\n    @bank, 1800, @cash => Bank\n    -@cash, @cash      => Cash\n    @loans             => Loans\n) => Sum\n\n\"let x\" = 1\n\n\"@x\" (\n) => Sum\n");
+        assert_eq!(Parser::new(&formatted).parse_document().unwrap(), document);
+
+        // the spans are written without the `let` lines
+        let spans = document.into_spans();
+        assert!(format(&spans).starts_with("(\n    @bank, 1800, @cash => Bank\n"));
+    }
+
+    #[test]
+    fn writes_templates() {
+        let test = "// per department\ntemplate department( title,base ) # t\n\n{title} (\n  {base}000..{base}499 => Sales\n) => Sum {title}\ntemplate empty()\n(\n) =>\n(\n  use department(\"Oslo office\",10) # o\n) => Sum\nuse  department(Bergen, 20)\nuse empty()\nTotal = Sum Bergen\n";
+        let document = Parser::new(test).parse_document().unwrap();
+        let formatted = format_document(&document);
+        assert_eq!(formatted, "// per department\ntemplate department(title, base) // t\n{title} (\n  {base}000..{base}499 => Sales\n) => Sum {title}\n\ntemplate empty()\n(\n) =>\n\n(\n    use department(\"Oslo office\", 10) // o\n) => Sum\n\nuse department(Bergen, 20)\n\nuse empty()\n\nTotal = Sum Bergen\n");
+        assert_eq!(Parser::new(&formatted).parse_document().unwrap(), document);
+
+        // the spans are written without the `template` lines
+        let spans = document.into_spans();
+        assert!(format(&spans).starts_with("(\n    use department(\"Oslo office\", 10) // o\n) => Sum\n"));
+    }
+
+    #[test]
+    fn writes_reports() {
+        let test = "// assets\nreport  balance{ # closing\n(\n1000..1999 => Assets\n) => Sum assets\n# end\n}\nreport result {\nlet sales = 3xxx\n(\n@sales => Sales\n) => Sum sales\n}\nResult = Sum sales\n\"report x {\" (\n3000 => A\n) => Sum\n\"}\" = 1\n";
+        let document = Parser::new(test).parse_document().unwrap();
+        let formatted = format_document(&document);
+        assert_eq!(formatted, "// assets\nreport balance { // closing\n    (\n        1000..1999 => Assets\n    ) => Sum assets\n\n    // end\n}\n\nreport result {\n    let sales = 3xxx\n\n    (\n        @sales => Sales\n    ) => Sum sales\n}\n\nResult = Sum sales\n\n\"report x {\" (\n    3000 => A\n) => Sum\n\n\"}\" = 1\n");
+        assert_eq!(Parser::new(&formatted).parse_document().unwrap(), document);
+
+        // two reports of the same kind are written as they are, the spans are written in one
+        let test = "report balance {\n(\n    1000 => A\n) => Sum\n} // a\nreport balance {\n}\n";
+        let document = Parser::new(test).parse_document().unwrap();
+        assert_eq!(format_document(&document), "report balance {\n    (\n        1000 => A\n    ) => Sum\n} // a\n\nreport balance {\n}\n");
+        assert_eq!(format(&document.spans()), "report balance {\n    (\n        1000 => A\n    ) => Sum\n}\n");
+    }
+
+    #[test]
+    fn writes_translations() {
+        let test = "Salg|en:Sales|de: \"Umsatz (netto)\"(\n3000 => Webshop |en: Web shop # c\n3100 => A | B\n3200 => \"A | en: B\"\n3300 =>|en:\n( 3400 => x\n) =>|en:Sub\n) => Sum salg | en: Sum sales\n|en: Other (\n3000 => x\n) =>\nBrutto|en:Gross = Sum salg\n";
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, "Salg | de: \"Umsatz (netto)\" | en: Sales (\n    3000 => Webshop | en: Web shop // c\n    3100 => A | B\n    3200 => \"A | en: B\"\n    3300 => | en:\n    (\n        3400 => x\n    ) => | en: Sub\n) => Sum salg | en: Sum sales\n\n| en: Other (\n    3000 => x\n) =>\n\nBrutto | en: Gross = Sum salg\n");
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+    }
+
+    #[test]
+    fn keeps_single_accounts() {
+        let test = "(\n3010 => Webshop\n3010..3010 => Webshop\n3020..4000 => Other sales\n) => Sum";
+        let spans = Parser::new(test).parse().unwrap();
+        let formatted = format(&spans);
+        assert_eq!(formatted, "(\n    3010       => Webshop\n    3010..3010 => Webshop\n    3020..4000 => Other sales\n) => Sum\n");
+        assert_eq!(Parser::new(&formatted).parse().unwrap(), spans);
+    }
+}
@@ -45,8 +45,8 @@
 //! ```
 //! 
 //! The groups can be nested to as many levels as you want (in the highly unlikely scnario that you
-//! create nesting above a few houndred levels this might cause a stack overflow since we parse
-//! these recursively):
+//! create nesting above `MAX_NESTING` (128) levels you'll get an error since we parse these
+//! recursively and would risk overflowing the stack):
 //! 
 //! ```ignore
 //! Other costs (
@@ -72,11 +72,138 @@
 //! =============================
 //! ```
 //! 
+//! A range title or sum label is the rest of the line after `=>`, and the `=>` has to be on the same
+//! line as the accounts or the `)` before it. The title can be left out, `) =>` is a span without a
+//! sum label, and the next line is never taken as the title then.
+//! 
+//! A range that only covers a single account can be written with just the account number, so
+//! `3010 => Webshop` is the same as `3010..3010 => Webshop`. The syntax tree remembers which way
+//! it was written so the formatter can write it back the same way.
+//! 
+//! A line can also list several ranges and accounts separated by commas, and take accounts out again
+//! with `!`:
+//! 
+//! ```ignore
+//! Sales (
+//!     3010                                 => Webshop
+//!     3000..3999, 8100, !3010, !3500..3599 => Other sales
+//! ) => Sum sales
+//! ```
+//! 
+//! "Other sales" is every account from 3000 to 3999 and 8100, except 3010 and 3500 to 3599. An
+//! account listed twice on the same line is only counted once and a line has to include at least one
+//! account that isn't excluded.
+//! 
+//! Charts of accounts are usually organised by the leading digits, so an account can also be a
+//! pattern. Every `x` in `30xx` stands for exactly one digit, so it matches the four digit accounts
+//! 3000 to 3099 but not 300 or 30000. `3*` matches every account starting with 3 no matter how many
+//! digits it has: 3, 30 to 39, 300 to 399 and so on. The patterns are matched against the account
+//! exactly as it's written, so `03xx` is 0300 to 0399, and the `x`s have to be at the end.
+//! 
+//! ```ignore
+//! Sales (
+//!     30xx, !3010 => Domestic sales
+//!     31xx, 8*    => Other sales
+//! ) => Sum sales
+//! ```
+//! 
+//! ### Signs
+//! 
+//! Revenue accounts usually have a negative balance in the ledger but are shown as positive numbers
+//! in a report. A `-` in front of a range line or a span header shows the amounts with the opposite
+//! sign:
+//! 
+//! ```ignore
+//! -Sales (
+//!     30xx  => Webshop
+//!     -3100 => Returns
+//! ) => Sum sales
+//! ```
+//! 
+//! For a span the sign of every line in it, its subspans and its total is reversed, so `Returns`
+//! above is reversed twice and shown with the sign it has in the ledger. The totals are always the
+//! sum of the amounts as they're shown, and computed lines use the totals as they're shown too.
+//! 
+//! ### Account identifiers
+//! 
+//! Accounts are `AccountId`s and are kept exactly as they're written, so `0150` and `150` are
+//! different accounts. By default only digits are allowed, but some ledgers use account codes like
+//! `1920.01` or `A-4000`. Those are allowed if you ask for them:
+//! 
+//! ```rust, ignore
+//! let spans = Parser::new(input).account_syntax(AccountSyntax::Alphanumeric).parse()?;
+//! ```
+//! 
+//! An account code starts with a letter or digit and can have `.`, `-` or `_` between the letters
+//! and digits. Accounts are ordered the way account codes are usually sorted: runs of digits by their
+//! value and everything else character by character. So `1920.01..1920.99` includes `1920.5`,
+//! `0150` is in `100..200` and `A-4000` comes after every account starting with a digit. Block titles
+//! can start with a letter too, a line is only an account line if it has a `=>` before any `(`.
+//! 
+//! ### Quoted titles
+//! 
+//! A header ends at the `(` and a title or sum label at the end of the line or a comment. Titles that
+//! need any of those characters are written in double quotes, where `\"`, `\\`, `\n` and `\t` are
+//! escapes:
+//! 
+//! ```ignore
+//! "Depreciation (machinery)" (
+//!     6000..6099 => "Leasing # 1" // a comment can still follow
+//! ) => "Sum depreciation (machinery)"
+//! ```
+//! 
+//! A quoted title is kept exactly as it's written, spaces included. A quote is only special at the
+//! start of a title, so unquoted titles can still have quotes in them.
+//! 
+//! ### Translations
+//! 
+//! The same report can be published in more than one language from one definition. A header, range
+//! title, sum label or computed line can have translations after it, each one written as `|`, the
+//! locale, `:` and the title:
+//! 
+//! ```ignore
+//! Salg | en: Sales | de: Umsatz (
+//!     3010 => Nettbutikk | en: Webshop
+//!     3020..4000 => Annet salg | en: Other sales
+//! ) => Sum salg | en: Sum sales
+//! ```
+//! 
+//! The title before the first `|` is the one in the language the definition is written in. The
+//! translations are kept as `Translations`, a map from the locale to the title, in the
+//! `name_translations` of a `Span`, the `translations` of a `Range` and a `Formula` and in the
+//! `SumType`. A locale starts with a language of two or three lowercase letters and can have a region
+//! like `en-GB` or `nb_NO`, anything else after a `|` is part of the title. A translated title can be
+//! in quotes too.
+//! 
+//! `localize` picks the title for the first of a list of locales it's translated to, where `en-GB`
+//! falls back to `en`, and the title itself if there are none.
+//! 
+//! ### Range sets
+//! 
+//! The same accounts are often used in more than one place, like the bank accounts in both the balance
+//! sheet and the cash flow. An account list can be given a name with `let` on a line of its own outside
+//! of the blocks, and range lines can use it as `@` and the name:
+//! 
+//! ```ignore
+//! let bank = 1900..1999, !1950
+//! 
+//! Assets (
+//!     @bank, 1800 => Bank and cash
+//! ) => Sum assets
+//! ```
+//! 
+//! A range set can be used before it's defined and in another file than the one defining it. The parser
+//! keeps where it's used in the `sets` of the `Range` and puts its accounts in the `accounts` when the
+//! whole input is parsed, `resolve_range_sets` does it again if you change the sets. The `let` lines
+//! aren't spans, so `parse` leaves them out and `parse_document` gives you them as `Item::RangeSet`s of
+//! a `Document`, see the syntax tree below. A range set can't use another one, and `validate_document`
+//! reports the ones that aren't defined or aren't used.
+//! 
 //! The full DSL looks like this
 //! 
 //! ```ignore
 //! Sales (
-//!     3010..3010 => Webshop
+//!     3010       => Webshop
 //!     3010..4000 => Other sales
 //! ) => Sum sales
 //! 
@@ -85,7 +212,7 @@
 //! ) => Sum material
 //! 
 //! (
-//!     5000..5000 => Direct labor
+//!     5000       => Direct labor
 //!     5010..6000 => Other labor costs
 //! ) => Sum labor costs
 //! 
@@ -98,6 +225,172 @@
 //! ) => Sum other costs
 //! ```
 //! 
+//! ## Computed lines
+//! 
+//! Lines like the gross profit or the operating result aren't the sum of any accounts but computed
+//! from other lines. They're written after a span as a title, `=` and an expression:
+//! 
+//! ```ignore
+//! Sales (
+//!     30xx => Webshop
+//! ) => Sum sales
+//! 
+//! (
+//!     4000..5000 => Material
+//! ) => Sum material
+//! 
+//! Gross profit = Sum sales - Sum material
+//! Gross margin = Gross profit / Sum sales * 100
+//! ```
+//! 
+//! An expression can use `+`, `-`, `*`, `/` and parentheses, numbers like `100` or `0.25` and refer to
+//! the total of a span by its sum label or to another computed line by its title. A name that has an
+//! operator or parenthesis in it, or looks like a number, is written in brackets like
+//! `[Sum non-operating costs]`. Computed lines are kept as a `Formula` in the `formulas` of the span
+//! before them, so the first one comes after a block, with the expression parsed into an `Expr` tree. A
+//! formula can refer to lines further down, but not to itself, and `validate` reports references to
+//! names that don't exist. A title or reference with `(` or `=` in it is written in quotes like
+//! `"Result (after tax)"`.
+//! 
+//! ## Comments
+//! 
+//! A comment starts with `//` or `#` and runs to the end of the line. Comments can be on their own
+//! line or after a header, a range or a sum label:
+//! 
+//! ```ignore
+//! // moved 3050 here after the 2019 audit
+//! Sales ( # domestic only
+//!     3010..3010 => Webshop // the new one
+//!     3020..4000 => Other sales
+//! ) => Sum sales
+//! ```
+//! 
+//! A `#` after something else on the line only starts a comment with whitespace on both sides of it,
+//! so `3000 => Account #5` is a range titled `Account #5`, while `Webshop # the new one` has a comment.
+//! 
+//! The parser skips the comments but keeps them in the syntax tree (`comments` are the lines before
+//! a span or range, `comment`, `header_comment` and `sum_comment` the ones at the end of a line) so
+//! they aren't lost if you write the definition back out.
+//! 
+//! ## Attributes
+//! 
+//! Hints for how a line is shown, like hiding it when it's zero or a reference to a note, are written
+//! as attributes on their own line before a range, a block or a computed line:
+//! 
+//! ```ignore
+//! #[page_break_before]
+//! Sales (
+//!     #[hide_if_zero, note = 7]
+//!     3010 => Webshop
+//!     #[label = "Other sales (domestic)", drilldown = false]
+//!     3020..4000 => Other sales
+//! ) => Sum sales
+//! ```
+//! 
+//! An attribute is a key with a value, which can be a word or number like `7` or `false` or a string
+//! in double quotes where `\"`, `\\`, `\n` and `\t` are escapes. A key without a value like
+//! `hide_if_zero` has the value `"true"`. They're kept in the `attributes` of the `Span`, `Range` or
+//! `Formula` as a map from key to value, the parser doesn't give them any meaning so renderers and
+//! other tools can use whichever they need. Since `#[` starts attributes, a `#` comment on its own
+//! line can't start with `[`.
+//! 
+//! ## Includes
+//! 
+//! Parts that are the same in several reports, like the equity section of a balance sheet, can be kept
+//! in a file of their own and included where they belong with `include` and the path in double quotes:
+//! 
+//! ```ignore
+//! // shared by all the balance sheets
+//! include "equity.qa"
+//! 
+//! Liabilities (
+//!     2400..2499 => Accounts payable
+//!     include "shared/long-term.qa"
+//! ) => Sum liabilities
+//! ```
+//! 
+//! The spans of the included file take the place of the `include` line, in a block they're subspans and
+//! a file with no blocks is an `EmptyInclude` error. A file included in a block can only have blocks
+//! and formulas. The files are read by the `Loader` given to the parser, without one an `include` is a
+//! `NoLoader` error. A file the loader can't read is an `IncludeFailed` error with the name of the file
+//! and why it couldn't be read in its `note`. `FileLoader` reads them from the file system relative to
+//! the file with the `include`, so give the parser the name of its file too, and `MemoryLoader` reads
+//! them from a map of names to text:
+//! 
+//! ```rust, ignore
+//! let spans = Parser::new(&source).file("reports/acme.qa").loader(FileLoader::new()).parse()?;
+//! ```
+//! 
+//! A file can't include itself, directly or through other files. The errors in an included file name
+//! the file they're in, like `file: equity.qa, line: 3, pos: 8`, and the spans from an included file
+//! have the name of the file their locations are in as their `file`, so the diagnostics from `validate`
+//! name it too. In the `Document` from `parse_document` an `include` line is an `Item::Include` with
+//! the items of the file, and the subspans from a file included in a block have the line in their
+//! `include`, so `format_document` writes the line back instead of the spans.
+//! 
+//! ## Templates
+//! 
+//! Consolidated reports often have the same block once for each department with only the title and
+//! the accounts changing. The block can be written once as a `template` with parameters, which are
+//! written as `{` and the name of the parameter and `}` in the block, and used with `use` and the
+//! arguments:
+//! 
+//! ```ignore
+//! template department(title, base)
+//! {title} (
+//!     {base}000..{base}499 => Sales
+//!     {base}500..{base}999 => Costs
+//! ) => Sum {title}
+//! 
+//! use department(Oslo, 10)
+//! use department("Bergen and Stavanger", 20)
+//! ```
+//! 
+//! The `use` line is replaced by the block with the arguments in place of the parameters, so the
+//! parser gives you the same `Span`s as if the blocks were written out and everything after it works
+//! with them as with any other block. A `use` line in a block gives a subspan, and a block in a
+//! template can use other templates but not itself. There is no space between the name and the `(`,
+//! and an argument is written in quotes unless it's a single word or number. A parameter can be used
+//! where an account is written or anywhere in a title or label, write `"{title}"` if a header can get
+//! a title with a `(` in it.
+//! 
+//! The expanded span has the `use` line in its `template_use` and every location in it is the location
+//! of the line, so `format` writes the `use` line back instead of the block. A template isn't a span,
+//! in the `Document` from `parse_document` it's an `Item::Template` with the block as it's written. A
+//! template has to be defined before it's used, in the same file or in a file included before, a `use`
+//! of a template that isn't defined is an `UndefinedTemplate` error. An error in the block a template
+//! expands to is reported at the `use` line, with the template and the argument it's in as the `note`
+//! of the error.
+//! 
+//! ## Reports
+//! 
+//! A balance sheet shows the balances at the end of a period and an income statement the movement in
+//! the period, so the same accounts add up to different amounts depending on the report. Top level
+//! spans can be wrapped in a report section saying which balances they use:
+//! 
+//! ```ignore
+//! report result {
+//!     Sales (
+//!         3000..3999 => Sales
+//!     ) => Sum sales
+//! }
+//! 
+//! report balance {
+//!     Bank (
+//!         1900..1999 => Bank
+//!     ) => Sum bank
+//! }
+//! ```
+//! 
+//! The spans in `report result` show the movement in the period (the closing balance minus the opening
+//! balance), the spans in `report opening` the opening balances and the spans in `report balance` the
+//! closing balances, like the spans outside a report. The kind is kept as a `ReportKind` in the
+//! `report` of every `Span` in the section, and `evaluate_period` picks the balances by it. A report
+//! can't be inside another report or a block, and the `}` is written on a line of its own. The spans of
+//! an included file keep the kinds of the reports in that file. In the `Document` from `parse_document`
+//! a section is an `Item::Report` with the items in it and the comments on and before the `report` and
+//! `}` lines, and a formula after the `}` isn't in the section.
+//! 
 //! ## Syntax tree
 //! 
 //! The DSL will be parsed into a syntax tree. Since the DSL and the syntax is so small
@@ -111,54 +404,48 @@
 //!         ranges: [
 //!             Range {
 //!                 title: "Webshop",
-//!                 from: 3010,
-//!                 to: 3010,
+//!                 accounts: [AccountRange { from: "3010", to: "3010", single_account: true }],
 //!             },
 //!             Range {
 //!                 title: "Other sales",
-//!                 from: 3010,
-//!                 to: 4000,
+//!                 accounts: [AccountRange { from: "3010", to: "4000" }],
 //!             },
 //!         ],
 //!         subspans: [],
-//!         sum_type: SumTotal(Some("Sum sales")),
+//!         sum_type: SumTotal { label: Some("Sum sales") },
 //!     },
 //!     Span {
 //!         name: None,
 //!         ranges: [
 //!             Range {
 //!                 title: "Material",
-//!                 from: 4000,
-//!                 to: 5000,
+//!                 accounts: [AccountRange { from: "4000", to: "5000" }],
 //!             },
 //!         ],
 //!         subspans: [],
-//!         sum_type: SumTotal(Some("Sum material")),
+//!         sum_type: SumTotal { label: Some("Sum material") },
 //!     },
 //!     Span {
 //!         name: None,
 //!         ranges: [
 //!             Range {
 //!                 title: "Direct labor",
-//!                 from: 5000,
-//!                 to: 5000,
+//!                 accounts: [AccountRange { from: "5000", to: "5000", single_account: true }],
 //!             },
 //!             Range {
 //!                 title: "Other labor costs",
-//!                 from: 5010,
-//!                 to: 6000,
+//!                 accounts: [AccountRange { from: "5010", to: "6000" }],
 //!             },
 //!         ],
 //!         subspans: [],
-//!         sum_type: SumTotal(Some("Sum labor costs")),
+//!         sum_type: SumTotal { label: Some("Sum labor costs") },
 //!     },
 //!     Span {
 //!         name: Some("Other costs"),
 //!         ranges: [
 //!             Range {
 //!                 title: "Leasing",
-//!                 from: 6000,
-//!                 to: 6010,
+//!                 accounts: [AccountRange { from: "6000", to: "6010" }],
 //!             },
 //!         ],
 //!         subspans: [
@@ -167,24 +454,75 @@
 //!                 ranges: [
 //!                     Range {
 //!                         title: "Office supplies",
-//!                         from: 6020,
-//!                         to: 6100,
+//!                         accounts: [AccountRange { from: "6020", to: "6100" }],
 //!                     },
 //!                     Range {
 //!                         title: "Consumables",
-//!                         from: 6100,
-//!                         to: 6200,
+//!                         accounts: [AccountRange { from: "6100", to: "6200" }],
 //!                     },
 //!                 ],
 //!                 subspans: [],
-//!                 sum_type: SubTotal(Some("Sum miscellaneous costs")),
+//!                 sum_type: SubTotal { label: Some("Sum miscellaneous costs") },
 //!             },
 //!         ],
-//!         sum_type: SumTotal(Some("Sum other costs")),
+//!         sum_type: SumTotal { label: Some("Sum other costs") },
 //!     },
 //! ]
 //! ```
 //! 
+//! Every `Span` and `Range` also records where in the source it was defined (the `location` of
+//! the header or range line and the `sum_location` of the `) => Sum` line). They're left out above
+//! for brevity but they make it possible to point back to the definition when you find problems
+//! after parsing.
+//! 
+//! `parse` gives you the spans, which is what you evaluate and render. `parse_document` gives you a
+//! `Document` with the `items` written outside of the blocks in the order they're written: an
+//! `Item::Span` for a block, an `Item::Formula` for a computed line, an `Item::RangeSet` for a `let`
+//! line, an `Item::Include` for an `include` line, an `Item::Template` for a template and an
+//! `Item::Report` for a report section. `Document::spans` gives you the spans `parse` gives you, with
+//! the spans of the included files and the reports in place of the includes and the reports and every
+//! formula in the `formulas` of the span before it.
+//! 
+//! ## Formatting
+//! 
+//! `format` writes spans back out as DSL text in a canonical layout: four spaces of indentation per
+//! level, the `=>` of the ranges in a block aligned and an empty line between top level spans. Spans
+//! and ranges also implement `Display` the same way. Parsing the output always gives you back the
+//! same syntax tree, so you can generate or edit report definitions programmatically. Titles are only
+//! written in quotes when they can't be read back without them:
+//! 
+//! ```rust, ignore
+//! let spans = Parser::new(input).parse()?;
+//! let formatted = format(&spans);
+//! assert_eq!(Parser::new(&formatted).parse()?, spans);
+//! ```
+//! 
+//! The `let`, `include` and `template` lines aren't spans, so `format` can't write them, and it writes
+//! the spans after each other with the same `report` in one section. `format_document` writes a
+//! `Document` with every item where it is and every section as it's written, and parsing its output
+//! gives you back the same document. `is_formatted` tells you if a text is already formatted the way
+//! `format_document` writes it, which is handy as a check in CI.
+//! 
+//! ## JSON
+//! 
+//! With the `serde` feature enabled `Document`, `Item`, `Include`, `Report`, `Span`, `Range`,
+//! `AccountRange`, `RangeSet`, `SetReference`, `Template`, `TemplateUse`, `ReportKind`, `SumType`,
+//! `Formula` and `Expr` implement `Serialize` and `Deserialize`. Attributes and translations are
+//! written as objects like `{ "note": "7" }` and `{ "en": "Sales" }`, a report kind as `"balance"`,
+//! `"result"` or `"opening"`. An `Item` is written as the item with a `"kind"` like `"span"` or
+//! `"range_set"` added to it, so the kind of a `Report` is written as its `"report"`. A `SumType` is
+//! written as `{ "kind": "sum_total", "label": "Sum sales" }` (or `"sub_total"` for nested spans),
+//! accounts as strings like `"0150"`, an account pattern like `30xx` as
+//! `{ "kind": "digits", "prefix": "30", "wildcards": 2 }`, an expression like `Sum sales * 2` as
+//! `{ "kind": "binary", "operator": "multiply", "left": .., "right": .. }` with numbers as strings like
+//! `"2.00"` and the comments are left out when there are none. Accounts can also be numbers when
+//! deserializing. The `location`s and comments are optional when deserializing. The shape of the spans
+//! is described by the JSON Schema in `schema/spans.schema.json`.
+//! 
+//! ```toml
+//! qa_parser = { version = "0.1", features = ["serde"] }
+//! ```
+//! 
 //! ## Error reporting
 //! 
 //! The error reporting tries to mimick that of Rusts:
@@ -216,428 +554,2985 @@
 //! 
 //! ERROR: Invalid range syntax
 //! ```
+//! 
+//! The error is a `ParseError` so if you need the details (for example to show the error in an
+//! editor) you can get the `kind` of error, the `position` (line, column and byte offset) and the
+//! `source_line` it occurred on directly:
+//! 
+//! ```rust, ignore
+//! let err = parser.parse().unwrap_err();
+//! assert_eq!(err.kind, ErrorKind::InvalidRangeSyntax);
+//! assert_eq!((err.line(), err.column()), (5, 22));
+//! ```
+//! 
+//! Parsing ends at the first line that isn't a block or one of the other lines described above, like a
+//! `)` without a block before it or text after the last block. `parse` returns the spans before it,
+//! `parse_recovering` below reports it as an `ExpectedBlock` error and continues on the next line.
+//! 
+//! `parse` stops at the first error. If you want to report every error in one go (for example to
+//! underline all of them in an editor) use `parse_recovering` which continues on the next line
+//! after an error and returns all the errors together with the spans it managed to parse:
+//! 
+//! ```rust, ignore
+//! let (spans, errors) = parser.parse_recovering();
+//! for e in &errors {
+//!     println!("{}", e);
+//! }
+//! ```
+//! 
+//! ## Validation
+//! 
+//! The parser only checks the syntax. `validate` checks the parsed spans for things that are probably
+//! mistakes, like reversed ranges (`4000..3000`), ranges that overlap so an account is counted twice,
+//! empty spans, missing sum labels, duplicate titles and computed lines that refer to names that don't
+//! exist or to themselves. `validate_document` checks a `Document` the same way, and also reports range
+//! sets that aren't defined, defined twice or not used and templates defined twice:
+//! 
+//! ```rust, ignore
+//! let document = Parser::new(input).parse_document()?;
+//! for diagnostic in validate_document(&document) {
+//!     println!("{}", diagnostic);
+//! }
+//! ```
+//! 
+//! Each `Diagnostic` has a severity and the `Location` of the problem. Use a `Validator` to turn
+//! rules off or change their severity.
+//! 
+//! ## Chart of accounts coverage
+//! 
+//! `coverage` checks the spans against the accounts that actually exist in the ledger. It tells you
+//! which accounts aren't captured by any range, which are captured by more than one range (and by
+//! which ones) and which ranges don't match any account. The chart of accounts can be read from a
+//! CSV file with `coverage::parse_chart_of_accounts`.
+//! 
+//! ```rust, ignore
+//! let chart = coverage::parse_chart_of_accounts(&csv)?;
+//! let coverage = coverage(&spans, &coverage::account_numbers(&chart));
+//! println!("Not in the report: {:?}", coverage.unmapped);
+//! ```
+//! 
+//! ## Evaluation
+//! 
+//! `evaluate` computes the amounts of a report from the balance of each account. The amount of a range
+//! is the sum of every account in its account list with both ends of a range included, so `3010..3010`
+//! is just the account 3010, and excluded accounts left out. The total of a span is the sum of its
+//! ranges and subspans. Amounts are exact decimals (`Amount`), not floats, so the totals always add up.
+//! The computed lines are evaluated from the totals, and have no amount if they can't be computed, for
+//! example when dividing by zero.
+//! 
+//! ```rust, ignore
+//! let balances = eval::parse_balances(&csv)?;
+//! for span in evaluate(&spans, &balances)? {
+//!     println!("{:?}: {}", span.span.sum_type, span.total);
+//! }
+//! ```
+//! 
+//! Amounts that get too large for an `Amount` are errors. `parse_balances` returns a `CsvError` for
+//! an account whose balances add up to more than it can hold, and `evaluate` an `EvalError` with
+//! where the range or span whose amount is too large is.
+//! 
+//! `evaluate` uses the same balances for every span. `evaluate_period` takes a `Period` with the
+//! opening and closing balances and evaluates each span with the balances of its report. Read them
+//! with `eval::parse_period` from a CSV file with the account, the opening and the closing balance:
+//! 
+//! ```rust, ignore
+//! let period = eval::parse_period(&csv)?;
+//! let report = evaluate_period(&spans, &period)?;
+//! ```
+//! 
+//! ## Rendering
+//! 
+//! `render` writes an evaluated report as plain text in the layout shown above. Use `render_with`
+//! and `RenderOptions` to change the width of the lines and the amount column, how amounts are
+//! aligned, the number of decimals, the thousands separator, the indentation of nested spans and the
+//! languages to write the titles in:
+//! 
+//! ```rust, ignore
+//! let report = evaluate(&spans, &balances)?;
+//! let options = RenderOptions {
+//!     width: 40,
+//!     thousands_separator: Some(' '),
+//!     decimal_point: ',',
+//!     locales: vec!["en".to_string()],
+//!     ..RenderOptions::default()
+//! };
+//! println!("{}", render_with(&report, &options));
+//! ```
+//! 
+//! ## Command line tool
+//! 
+//! The `qa` tool is built with the `cli` feature:
+//! 
+//! ```text
+//! cargo install --path . --features cli
+//! ```
+//! 
+//! `qa check` parses and validates definitions and prints every problem it finds, `qa fmt` formats
+//! them in place (`qa fmt --check` only lists the files that aren't formatted), `qa dump` prints the
+//! syntax tree as JSON and `qa render` prints the report with the balances from a CSV file:
+//! 
+//! ```text
+//! qa check report.qa
+//! qa fmt --check report.qa
+//! qa dump report.qa > report.json
+//! qa render --width 40 --thousands " " --locale en report.qa balances.csv
+//! ```
+//! 
+//! `qa check` and `qa fmt --check` exit with 1 if there are errors or unformatted files, so they can be
+//! used in CI. Add `--alphanumeric-accounts` to any command if the definitions use account codes like
+//! `A-4000`. Included files are read relative to the file including them, `qa fmt` reads them too but
+//! only formats the files it's given. `qa render --period` reads the opening and closing balances from
+//! the CSV file and shows the spans of each report with the balances of its kind. Run `qa help` for all
+//! the options.
+
+mod account;
+mod amount;
+pub mod coverage;
+mod csv;
+mod document;
+mod error;
+pub mod eval;
+mod formatter;
+mod loader;
+mod locale;
+mod location;
+mod range_set;
+pub mod render;
+pub mod validate;
+
+pub use account::{AccountId, AccountSyntax, ParseAccountError};
+pub use amount::{Amount, ParseAmountError};
+pub use coverage::{coverage, Coverage};
+pub use csv::CsvError;
+pub use document::{Document, Include, Item, Report};
+pub use error::{ErrorKind, ParseError};
+pub use eval::{evaluate, evaluate_period, EvalError, EvaluatedFormula, EvaluatedRange, EvaluatedSpan};
+pub use formatter::{format, format_document, is_formatted};
+pub use loader::{FileLoader, Loader, MemoryLoader};
+pub use locale::{localize, Translations};
+pub use location::{Location, Position};
+pub use range_set::resolve_range_sets;
+pub use render::{render, render_with, RenderOptions};
+pub use validate::{validate, validate_document, Diagnostic, Rule, Severity, Validator};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use document::{all_spans_mut, has_span};
+use loader::SharedLoader;
+use locale::translation_start;
+
+type AppErr = ErrorKind;
+
+/// The maximum number of levels blocks can be nested.
+pub const MAX_NESTING: usize = 128;
+
+/// The keyword of an `include "path.qa"` line.
+const INCLUDE: &str = "include";
+
+/// The keyword of a `let bank = 1900..1999` line.
+const LET: &str = "let";
+
+/// The keyword of a `template department(title, base)` line.
+const TEMPLATE: &str = "template";
+
+/// The keyword of a `use department(Sales, 30)` line.
+const USE: &str = "use";
 
-type AppErr = &'static str;
+/// The keyword of a `report balance {` line.
+const REPORT: &str = "report";
 
 #[derive(Debug)]
 pub struct Parser {
     input: Vec<char>,
+    /// The index in `input` and the byte offset of the first character of every line, so the
+    /// position of the cursor can be found without going through all the input before it.
+    line_starts: Vec<(usize, usize)>,
     cursor: usize,
+    recovering: bool,
+    errors: Vec<ParseError>,
+    depth: usize,
+    /// Comment lines we have read but not yet attached to a node in the syntax tree.
+    comments: Vec<String>,
+    /// Attributes we have read but not yet attached to a node in the syntax tree.
+    attributes: Attributes,
+    account_syntax: AccountSyntax,
+    /// The name of the file the input is from.
+    file: Option<String>,
+    /// Reads the files of the `include "path.qa"` lines, they can't be read without one.
+    loader: Option<SharedLoader>,
+    /// The files that include this one, nearest last.
+    includes: Vec<String>,
+    /// True if the input is an included file. The range sets are resolved when the whole input
+    /// with the files it includes is parsed.
+    included: bool,
+    /// True if the input is a file included in a block, it can only have blocks and formulas.
+    in_block: bool,
+    /// The templates defined so far by their name, they're shared with the included files.
+    templates: HashMap<String, Template>,
+    /// The parameters of the template whose body we're reading. `{parameter}` is accepted where
+    /// an account can be written then, and templates aren't expanded.
+    parameters: Option<Vec<String>>,
+    /// The templates we're expanding, innermost last.
+    expanding: Vec<String>,
+    /// The note of the next error reported, like why an included file couldn't be read.
+    note: Option<String>,
+}
+
+/// The parts of `-Sales ( // comment`
+struct BlockStart {
+    name: Option<String>,
+    translations: Translations,
+    reverse_sign: bool,
+    location: Location,
+    comment: Option<String>,
+}
+
+/// A `report balance {` line we haven't found the `}` of yet.
+struct Section {
+    report: Report,
+    /// Where the `report` line starts.
+    start: usize,
+}
+
+/// The parts of `use department(Sales, 30) // comment`
+struct UseLine {
+    template_use: TemplateUse,
+    /// Where the `use` line starts.
+    start: usize,
+    location: Location,
+}
+
+/// The parts of `) => Sum sales // comment`
+struct BlockEnd {
+    name: Option<String>,
+    translations: Translations,
+    location: Location,
+    comment: Option<String>,
 }
 
 impl Parser {
     /// Creates a new parser. This method will duplicate the passed in string as an array.
     pub fn new(input: &str) -> Self {
+        let input = input.chars().collect::<Vec<char>>();
+        let mut line_starts = vec![(0, 0)];
+        let mut offset = 0;
+        for (i, c) in input.iter().enumerate() {
+            offset += c.len_utf8();
+            if *c == '\n' {
+                line_starts.push((i + 1, offset));
+            }
+        }
+
         Parser {
-            input: input.chars().collect::<Vec<char>>(),
+            input,
+            line_starts,
             cursor: 0,
+            recovering: false,
+            errors: vec![],
+            depth: 0,
+            comments: vec![],
+            attributes: Attributes::new(),
+            account_syntax: AccountSyntax::Digits,
+            file: None,
+            loader: None,
+            includes: vec![],
+            included: false,
+            in_block: false,
+            templates: HashMap::new(),
+            parameters: None,
+            expanding: vec![],
+            note: None,
         }
     }
 
-    /// Parses the text returning a Vec<Span> or an formatted error message.
-    pub fn parse(&mut self) -> Result<Vec<Span>, String> {
-        let mut spans = vec![];
-
-        loop {
-            match self.block(false) {
-                Ok(span_res) => {
-                    match span_res {
-                        Some(span) => spans.push(span),
-                        None => break,
-                    }
-                }
-
-                Err(e) => {
-                    let formatted_e = self.report_err(e);
-                    return Err(formatted_e);
-                },
-            }
-        }
+    /// Sets which account identifiers are accepted, only digits (the default) or codes like
+    /// `1920.01` and `A-4000`.
+    ///
+    /// ```rust, ignore
+    /// let spans = Parser::new(input).account_syntax(AccountSyntax::Alphanumeric).parse()?;
+    /// ```
+    pub fn account_syntax(mut self, syntax: AccountSyntax) -> Self {
+        self.account_syntax = syntax;
+        self
+    }
 
-        Ok(spans)
+    /// Sets the name of the file the input is from. It's shown in the errors and the files it
+    /// includes are found from it.
+    pub fn file(mut self, name: &str) -> Self {
+        self.file = Some(name.to_string());
+        self
     }
 
-    fn block(&mut self, sub: bool) -> Result<Option<Span>, AppErr> {
-        // This is just for debugging convenience, paste this to see the state of the parser
-        // println!("cursor: {}\n{}", self.cursor, &self.input[self.cursor..].iter().collect::<String>());
-        
-        // Sales (
-        let block_start = match self.block_start() {
-            Ok(name) => name,
-            Err(e) => return Err(e),
-        };
+    /// Sets the loader that reads the files of the `include "path.qa"` lines. The spans of an
+    /// included file take the place of the include, without a loader an include is a `NoLoader`
+    /// error.
+    ///
+    /// ```rust, ignore
+    /// let spans = Parser::new(&source).file("reports/acme.qa").loader(FileLoader::new()).parse()?;
+    /// ```
+    pub fn loader<L: Loader + 'static>(mut self, loader: L) -> Self {
+        self.loader = Some(SharedLoader(Arc::new(loader)));
+        self
+    }
 
-        let name = match block_start {
-            Some(name) => name,
-            None => return Ok(None),
-        };
+    /// Parses the text returning a Vec<Span> or a `ParseError` describing what went wrong and
+    /// where. Use `to_string()` on the error to get a formatted error message.
+    ///
+    /// The spans are the blocks of the input with the formulas after them, the `let`, `include`
+    /// and `template` lines are only in the `Document` from `parse_document`.
+    pub fn parse(&mut self) -> Result<Vec<Span>, ParseError> {
+        self.parse_document().map(Document::into_spans)
+    }
 
-        // *' ' | '\n' * n..y *i \n
-        let mut ranges: Vec<Range> = vec![];
-        loop {
-            match self.range()? {
-                Some(range) => ranges.push(range),
-                None => break,
+    /// Parses the text like `parse`, returning every line outside of the blocks in the order it's
+    /// written.
+    pub fn parse_document(&mut self) -> Result<Document, ParseError> {
+        let mut document = Document::default();
+        match self.items(&mut document) {
+            Ok(()) => {
+                if !self.included {
+                    document.resolve_range_sets();
+                }
+                Ok(document)
             }
+            // an error in an included file is reported by the parser of that file
+            Err(e) => Err(self.errors.pop().unwrap_or_else(|| self.report_err(e))),
         }
+    }
 
-        
+    /// Parses the text without stopping at the first error. When an error is found it's recorded
+    /// and the parser continues on the next line, so you get every error in the input together
+    /// with a best-effort syntax tree of everything that could be parsed.
+    ///
+    /// If the returned errors are empty the spans are the same as the ones `parse` would return.
+    pub fn parse_recovering(&mut self) -> (Vec<Span>, Vec<ParseError>) {
+        let (document, errors) = self.parse_document_recovering();
+        (document.into_spans(), errors)
+    }
 
-        // * ' ' (
-        let mut subspans = vec![];
-        while let Some(span) = self.block(true)? {
-            subspans.push(span);
+    /// Parses the text like `parse_recovering`, returning every line outside of the blocks like
+    /// `parse_document`.
+    pub fn parse_document_recovering(&mut self) -> (Document, Vec<ParseError>) {
+        self.recovering = true;
+        let mut document = Document::default();
+        // the items before an error we can't continue after, like blocks nested too deep, are kept
+        if let Err(e) = self.items(&mut document) {
+            let err = self.report_err(e);
+            self.errors.push(err);
         }
+        if !self.included {
+            document.resolve_range_sets();
+        }
+        self.recovering = false;
 
-        
-
-        // ) => *char
-        let sum_name = self.block_end()?;
+        let errors = std::mem::take(&mut self.errors);
+        (document, errors)
+    }
 
-        let sumtype = if sub {
-            SumType::SubTotal(sum_name)
-        } else {
-            SumType::SumTotal(sum_name)
-        };
+    /// Parses the input into `document`, the items parsed before an error are left there.
+    fn items(&mut self, document: &mut Document) -> Result<(), AppErr> {
+        let mut section: Option<Section> = None;
 
-        let span = Span {
-            name,
-            ranges,
-            subspans,
-            sum_type: sumtype,
-        };
+        loop {
+            // the items of an open report are in the report
+            let in_report = section.is_some();
+            let items = match &mut section {
+                Some(open) => &mut open.report.items,
+                None => &mut document.items,
+            };
 
-        
-        Ok(Some(span))
-    }
+            // include "equity.qa"
+            match self.include(false) {
+                Ok(Some(include)) => {
+                    items.push(Item::Include(include));
+                    continue;
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
+            }
 
-    /// ) => *char \n
-    fn block_end(&mut self) -> Result<Option<String>, AppErr> {
-        let mut name = String::new();
-        let mut is_block_end = false;
+            // let bank = 1900..1999, !1950
+            match self.range_set() {
+                Ok(Some(set)) => {
+                    items.push(Item::RangeSet(set));
+                    continue;
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
+            }
 
-        self.skip_ws_and_nl();
-        while let Some(c) = self.next() {
-            match c {
-                ')' => {
-                    while let Some(ch) = self.next() {
-                        match ch {
-                            ' ' => (),
-                            '=' => match self.peek(1) {
-                                Some('>') => {
-                                    let _ = self.next();
-                                    is_block_end = true;
-                                    break;
-                                }
-
-                                Some(_) => return Err("Expected >"),
-                                _ => return Err("Expected => after )"),
-                            },
-                            _ => return Ok(None),
-                        }
-                    }
+            // template department(title, base)
+            match self.template() {
+                Ok(Some(template)) => {
+                    items.push(Item::Template(template));
+                    continue;
                 }
+                Ok(None) => (),
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
+            }
 
-                _ => break,
+            // use department(Sales, 30)
+            match self.template_use(false) {
+                Ok(Some(span)) => {
+                    items.push(Item::Span(span));
+                    continue;
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
             }
-        }
 
-        if !is_block_end {
-            return Ok(None)
-        }
+            // report balance {
+            match self.report_start(in_report) {
+                Ok(Some(open)) => {
+                    section = Some(open);
+                    continue;
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
+            }
 
-        // We know that we have ) =>
+            // } comment? \n
+            if self.peek(1) == Some('}') {
+                let mut report = match section.take() {
+                    Some(open) => open.report,
+                    None => {
+                        self.recover(ErrorKind::UnexpectedSyntax)?;
+                        continue;
+                    }
+                };
 
-        self.skip_ws();
-        let mut skip_ws = true;
+                let _ = self.next();
+                let comment = self.end_of_line();
+                self.close_report(&mut report);
+                report.end_comment = comment.clone().unwrap_or_default();
+                document.items.push(Item::Report(report));
+                if let Err(e) = comment {
+                    self.recover(e)?;
+                }
+                continue;
+            }
 
-        while let Some(c) = self.next() {
-            match c {
-                '\n' => {
-                    if !skip_ws {
-                        break;
+            if let Some(span) = self.block(false)? {
+                items.push(Item::Span(span));
+                continue;
+            }
+
+            // Gross profit = Sum sales - Sum material
+            self.skip_ws_and_nl();
+            // in a report the formula belongs to a span in the report
+            let after_span = has_span(items);
+            if !after_span && self.at_formula() {
+                // a formula belongs to the span before it
+                self.recover(ErrorKind::FormulaBeforeBlock)?;
+                continue;
+            }
+            if after_span {
+                match self.formula() {
+                    Ok(Some(formula)) => {
+                        items.push(Item::Formula(formula));
+                        continue;
                     }
-                },
-                '\r' => match self.peek(1) {
-                    Some('\n') => {
-                        let _ = self.next();
-                        if !skip_ws {
-                            break;
-                        }
+                    Ok(None) => (),
+                    Err(e) => {
+                        self.recover(e)?;
+                        continue;
                     }
-                    _ => {
-                        skip_ws = false;
-                        name.push(c);
-                    },
-                },
+                }
+            }
 
-                _ => {
-                    skip_ws = false;
-                    name.push(c);
-                },
+            // parse stops at anything left that is not a block, parse_recovering reports it and
+            // goes on with the next line
+            self.skip_ws_and_nl();
+            if self.peek(1).is_none() || !self.recovering {
+                if let Some(Section { mut report, start }) = section.take() {
+                    let end = self.cursor;
+                    self.close_report(&mut report);
+                    document.items.push(Item::Report(report));
+                    self.cursor = start;
+                    let unclosed = self.recover(ErrorKind::UnclosedReport);
+                    self.cursor = end;
+                    unclosed?;
+                }
+
+                // the comments before a missing `}` are already in the report
+                document.trailing_comments.append(&mut self.comments);
+                break;
             }
-        }
 
-        // remove any trailing whitespace
-        let name = name.trim_end().to_string();
+            self.recover(ErrorKind::ExpectedBlock)?;
+        }
 
-        Ok(Some(name))
+        Ok(())
     }
 
-    /// chars*(
-    /// Returns an error if there is a parse error in a block.
-    /// The next is an Option which indicates if there is a "block start" or not
-    /// The last option is to indicate if there is a title/header for the block or not
-    fn block_start(&mut self) -> Result<Option<Option<String>>, AppErr> {
-        let mut name = String::new();
-        let mut is_block_start = false;
-        let mut lookahed = 1;
-        while let Some(c) = self.peek(lookahed) {
-            match c {
-                '(' => {
-                    is_block_start = true;
-                    break;
-                },
+    fn block(&mut self, sub: bool) -> Result<Option<Span>, AppErr> {
+        // This is just for debugging convenience, paste this to see the state of the parser
+        // println!("cursor: {}\n{}", self.cursor, &self.input[self.cursor..].iter().collect::<String>());
+        
+        // #[bold]
+        self.attributes()?;
 
-                ')' | '=' => {
-                    return Ok(None)
-                },
+        // Sales (
+        let block_start = match self.block_start() {
+            Ok(Some(block_start)) => block_start,
+            Ok(None) => return Ok(None),
 
-                _ => (),
+            // we know there is a `(` so we parse the rest as a block without a title
+            Err(e) => {
+                let location = self.location(self.cursor, self.cursor);
+                self.recover(e)?;
+                BlockStart {
+                    name: None,
+                    translations: Translations::new(),
+                    reverse_sign: false,
+                    location,
+                    comment: None,
+                }
             }
+        };
+        let comments = std::mem::take(&mut self.comments);
+        let attributes = std::mem::take(&mut self.attributes);
 
-            lookahed += 1;
+        // we parse nested blocks recursively so we need to stop before we overflow the stack
+        if self.depth >= MAX_NESTING {
+            return Err(ErrorKind::NestingTooDeep);
         }
+        self.depth += 1;
+        let body = self.block_body();
+        self.depth -= 1;
+        let (ranges, subspans, block_end) = body?;
 
-        // if we got all the way to the end without finding a `(` we know this is not a block
-        // but it's not an error
-        if !is_block_start {
+        // comments right before the `)`
+        let end_comments = std::mem::take(&mut self.comments);
+
+        let sumtype = if sub {
+            SumType::SubTotal { label: block_end.name, translations: block_end.translations }
+        } else {
+            SumType::SumTotal { label: block_end.name, translations: block_end.translations }
+        };
+
+        let span = Span {
+            name: block_start.name,
+            name_translations: block_start.translations,
+            ranges,
+            subspans,
+            sum_type: sumtype,
+            reverse_sign: block_start.reverse_sign,
+            location: block_start.location,
+            sum_location: block_end.location,
+            comments,
+            attributes,
+            header_comment: block_start.comment,
+            end_comments,
+            sum_comment: block_end.comment,
+            formulas: vec![],
+            trailing_comments: vec![],
+            include: None,
+            template_use: None,
+            report: None,
+            file: None,
+        };
+
+        Ok(Some(span))
+    }
+
+    /// The ranges and subspans of a block and its `) => label`. Called with the depth of the block
+    /// counted, so the caller can put it back whichever way this returns.
+    fn block_body(&mut self) -> Result<(Vec<Range>, Vec<Span>, BlockEnd), AppErr> {
+        let mut ranges: Vec<Range> = vec![];
+        let mut subspans = vec![];
+
+        // when recovering we continue parsing the block after an error on a line, so we loop
+        // until we find the end of the block
+        let block_end = loop {
+            // *' ' | '\n' * n..y *i \n
+            loop {
+                match self.range() {
+                    Ok(Some(range)) => ranges.push(range),
+                    Ok(None) => break,
+                    Err(e) => self.recover(e)?,
+                }
+            }
+
+            // * ' ' ( | include "path" | use name(arguments)
+            loop {
+                match self.include(true) {
+                    Ok(Some(mut include)) => {
+                        // the spans of the file are written as the line they're included by
+                        let document = std::mem::take(&mut include.document);
+                        for mut span in document.into_spans() {
+                            span.include = Some(include.clone());
+                            subspans.push(span);
+                        }
+                        continue;
+                    }
+                    Ok(None) => (),
+                    Err(e) => {
+                        self.recover(e)?;
+                        continue;
+                    }
+                }
+
+                if self.parameters.is_some() {
+                    // the templates aren't expanded in the body of a template, it's only checked
+                    match self.use_line() {
+                        Ok(Some(_)) => continue,
+                        Ok(None) => (),
+                        Err(e) => {
+                            self.recover(e)?;
+                            continue;
+                        }
+                    }
+                }
+
+                match self.template_use(true) {
+                    Ok(Some(span)) => {
+                        subspans.push(span);
+                        continue;
+                    }
+                    Ok(None) => (),
+                    Err(e) => {
+                        self.recover(e)?;
+                        continue;
+                    }
+                }
+
+                match self.block(true)? {
+                    Some(span) => subspans.push(span),
+                    None => break,
+                }
+            }
+
+            // ) => *char
+            match self.block_end() {
+                Ok(block_end) => break block_end,
+
+                // there is something else than a block end here, we try to parse the rest of
+                // the block on the next line
+                Err(ErrorKind::ExpectedCloseParen) if self.peek(1).is_some() => {
+                    self.recover(ErrorKind::ExpectedCloseParen)?;
+                }
+
+                Err(e) => {
+                    let location = self.location(self.cursor, self.cursor);
+                    self.recover(e)?;
+                    break BlockEnd {
+                        name: None,
+                        translations: Translations::new(),
+                        location,
+                        comment: None,
+                    };
+                }
+            }
+        };
+
+        Ok((ranges, subspans, block_end))
+    }
+
+    /// include "path" comment? \n
+    /// Returns the line with the items of the included file. The spans of a file included in a
+    /// block are made subspans.
+    fn include(&mut self, sub: bool) -> Result<Option<Include>, AppErr> {
+        self.attributes()?;
+        if !self.at_include() {
             return Ok(None);
         }
 
-        self.skip_ws_and_nl();
-        while let Some(c) = self.next() {
-            match c {
-                '(' => break,
-                _ => name.push(c),
+        let start = self.cursor;
+        if !self.attributes.is_empty() {
+            // attributes belong to a line of the report, and an include isn't one
+            self.attributes.clear();
+            return Err(ErrorKind::DanglingAttributes);
+        }
+
+        self.cursor += INCLUDE.len();
+        self.skip_ws();
+        let path = self.quoted()?;
+        let location = self.location(start, self.cursor);
+        let comment = self.end_of_line()?;
+        let comments = std::mem::take(&mut self.comments);
+
+        let loader = match &self.loader {
+            Some(loader) => loader.clone(),
+            None => {
+                self.cursor = start;
+                return Err(ErrorKind::NoLoader);
             }
+        };
+
+        // the name of the input is the one it was given, the included files have resolved names
+        let file = match &self.file {
+            Some(file) if !self.included => Some(loader.0.resolve(file, None)),
+            file => file.clone(),
+        };
+        let name = loader.0.resolve(&path, self.file.as_deref());
+        if file.as_ref() == Some(&name) || self.includes.contains(&name) {
+            self.cursor = start;
+            return Err(ErrorKind::CircularInclude);
+        }
+        if self.includes.len() >= MAX_NESTING {
+            self.cursor = start;
+            return Err(ErrorKind::NestingTooDeep);
         }
+        let text = match loader.0.load(&name) {
+            Ok(text) => text,
+            Err(e) => {
+                self.cursor = start;
+                self.note = Some(format!("{}: {}", name, e));
+                return Err(ErrorKind::IncludeFailed);
+            }
+        };
 
-       
-        if name.is_empty() {
-            Ok(Some(None))
+        let mut includes = self.includes.clone();
+        includes.extend(file);
+        let mut parser = Parser {
+            depth: self.depth,
+            account_syntax: self.account_syntax,
+            file: Some(name.clone()),
+            loader: Some(loader),
+            includes,
+            included: true,
+            in_block: self.in_block || sub,
+            templates: self.templates.clone(),
+            expanding: self.expanding.clone(),
+            ..Parser::new(&text)
+        };
+
+        let mut document = if self.recovering {
+            let (document, errors) = parser.parse_document_recovering();
+            self.errors.extend(errors);
+            document
         } else {
-            let name = name.trim_end().to_string();
-            Ok(Some(Some(name)))
-        }
-    }
+            match parser.parse_document() {
+                Ok(document) => document,
+                Err(e) => {
+                    let kind = e.kind;
+                    self.errors.push(e);
+                    return Err(kind);
+                }
+            }
+        };
 
-    fn is_space_or_newline(c: char) -> bool {
-        c.is_whitespace() || c.is_control()
-    }
+        // the templates defined in the file can be used after the include
+        self.templates = std::mem::take(&mut parser.templates);
 
-    fn skip_ws(&mut self) {
-        while let Some(c) = self.peek(1) {
-            if c.is_whitespace() {
-                let _ = self.next();
-            } else {
-                break;
+        if sub && !has_span(&document.items) {
+            // there would be nothing in the block to keep the line with
+            self.cursor = start;
+            return Err(ErrorKind::EmptyInclude);
+        }
+        for span in all_spans_mut(&mut document.items) {
+            span.set_file(&name);
+            if sub {
+                span.sum_type.make_sub_total();
             }
         }
+
+        Ok(Some(Include { path, file: name, location, comments, comment, document }))
     }
 
-    fn skip_ws_and_nl(&mut self) {
-        while let Some(c) = self.peek(1) {
-            if Parser::is_space_or_newline(c) {
-                let _ = self.next();
-            } else {
-                break;
-            }
-        }
+    fn at_include(&self) -> bool {
+        is_include(self.input[self.cursor.min(self.input.len())..].iter().copied())
     }
-    /// int* .. int* ' '* => ' '* char* /n
-    fn range(&mut self) -> Result<Option<Range>, AppErr> {
-        // 1111
-        self.skip_ws_and_nl();
-        let range_start = match self.check_range_part()? {
-            Some(range) => range,
-            None => return Ok(None),
-        };
 
-        // ..
-        for _ in 0..2 {
-            match self.next().unwrap() {
-                '.' => (),
-                _ => {
-                    // we need to decrease the cursor since we already moved past the error
-                    self.cursor -= 1;
-                    return Err("Invalid range syntax");
-                },
-            }
+    /// let name = accounts (, accounts)* comment? \n
+    fn range_set(&mut self) -> Result<Option<RangeSet>, AppErr> {
+        self.attributes()?;
+        if !self.at_let() {
+            return Ok(None);
         }
 
-        // 1111
-        let range_end = match self.check_range_part()? {
-            Some(range) => range,
-            None => return Err("Invalid range"),
-        };
+        let start = self.cursor;
+        if !self.attributes.is_empty() {
+            // attributes belong to a line of the report, and a range set isn't one
+            self.attributes.clear();
+            return Err(ErrorKind::DanglingAttributes);
+        }
+        if self.in_block {
+            return Err(ErrorKind::ExpectedBlock);
+        }
 
-        // =>
+        self.cursor += LET.len();
         self.skip_ws();
-        while let Some(c) = self.next() {
-            match c {
-                '=' => match self.peek(1) {
-                    Some('>') => {
-                        let _ = self.next();
-                        break;
-                    }
-                    Some(_) => {
-                        return Err("Invalid syntax after =");
-                    },
-                    None => return Err("Unexpected EOF"),
-                },
+        let name = self.name()?;
 
-                _ => return Err("Unexpected syntax"),
-            }
+        // at_let has seen the `=`
+        self.skip_ws();
+        let _ = self.next();
+        self.skip_ws();
+
+        let accounts = self.account_list(None)?;
+        let location = self.location(start, self.cursor);
+        let comment = self.end_of_line()?;
+        let comments = std::mem::take(&mut self.comments);
+
+        Ok(Some(RangeSet { name, accounts, location, comments, comment }))
+    }
+
+    fn at_let(&self) -> bool {
+        is_let(self.input[self.cursor.min(self.input.len())..].iter().copied())
+    }
+
+    /// The name of a range set after `let` or `@`, or of a template or parameter.
+    fn name(&mut self) -> Result<String, AppErr> {
+        let name = self.attribute_token(is_name_char);
+        if name.is_empty() {
+            return Err(ErrorKind::ExpectedName);
         }
+        Ok(name)
+    }
 
-        // Title
-        let mut title = String::new();
-        self.skip_ws();
-        while let Some(c) = self.next() {
-            match c {
-                '\n' => break,
+    /// template name(parameter (, parameter)*) comment? \n block
+    /// The block is checked with `{parameter}` accepted where an account can be written, and kept
+    /// as it's written so it can be parsed with the arguments of a `use` line in place.
+    fn template(&mut self) -> Result<Option<Template>, AppErr> {
+        self.attributes()?;
+        if !self.at_template() {
+            return Ok(None);
+        }
 
-                '\r' => {
-                    if let Some(c) = self.peek(1) {
-                        if c == '\n' {
-                            self.next();
-                            break;
-                        } else {
-                            title.push(self.next().unwrap());
-                        }
-                    }
-                }
+        let start = self.cursor;
+        if !self.attributes.is_empty() {
+            // attributes belong to a line of the report, the block of the template can have them
+            self.attributes.clear();
+            return Err(ErrorKind::DanglingAttributes);
+        }
+        if self.in_block {
+            return Err(ErrorKind::ExpectedBlock);
+        }
 
-                _ => title.push(c),
-            }
+        self.cursor += TEMPLATE.len();
+        self.skip_ws();
+        let name = self.name()?;
+        let parameters = self.list(Parser::name)?;
+        let mut seen = HashSet::new();
+        if !parameters.iter().all(|parameter| seen.insert(parameter)) {
+            self.cursor = start;
+            return Err(ErrorKind::DuplicateParameter);
         }
+        let location = self.location(start, self.cursor);
+        let comment = self.end_of_line()?;
+        let comments = std::mem::take(&mut self.comments);
 
-        // remove any trailing spaces
-        let title = title.trim_end().to_string();
+        // Department {title} (
+        let body_start = self.cursor;
+        self.parameters = Some(parameters.clone());
+        let block = self.block(false);
+        self.parameters = None;
+        if block?.is_none() {
+            return Err(ErrorKind::ExpectedBlock);
+        }
 
-        let from: u32 = range_start.parse().expect("Not a number");
-        let to: u32 = range_end.parse().expect("Not a number");
+        // without the empty lines before the block and the line break after it
+        let end = self.cursor.min(self.input.len());
+        let body: String = self.input[body_start.min(end)..end].iter().collect();
+        let body = body.trim_end();
+        let leading = body.len() - body.trim_start().len();
+        let body = body[body[..leading].rfind('\n').map_or(0, |i| i + 1)..].to_string();
 
-        let range = Range { title, from, to };
+        let template = Template { name, parameters, body, location, comments, comment };
+        // the first template with a name is the one that's used
+        if !self.templates.contains_key(&template.name) {
+            self.templates.insert(template.name.clone(), template.clone());
+        }
 
-        Ok(Some(range))
+        Ok(Some(template))
     }
 
-    fn check_range_part(&mut self) -> Result<Option<String>, AppErr> {
-        let mut from = String::new();
+    fn at_template(&self) -> bool {
+        is_template(self.input[self.cursor.min(self.input.len())..].iter().copied())
+    }
 
-        let rangeint = match self.peek(1) {
-            Some(r) => r,
+    /// use name(argument (, argument)*) comment? \n
+    /// Returns the block the template expands to with the arguments. An error in the block is
+    /// reported at the `use` line with the template and argument it's from in its `note`.
+    fn template_use(&mut self, sub: bool) -> Result<Option<Span>, AppErr> {
+        let UseLine { template_use, start, location } = match self.use_line()? {
+            Some(line) => line,
             None => return Ok(None),
         };
+        let comments = std::mem::take(&mut self.comments);
+
+        let template = match self.templates.get(&template_use.name) {
+            Some(template) => template.clone(),
+            None => {
+                self.cursor = start;
+                return Err(ErrorKind::UndefinedTemplate);
+            }
+        };
+        if template.parameters.len() != template_use.arguments.len() {
+            self.cursor = start;
+            return Err(ErrorKind::ArgumentCount);
+        }
+        if self.expanding.contains(&template.name) {
+            self.cursor = start;
+            return Err(ErrorKind::CircularTemplate);
+        }
+        // the arguments can't add lines to the block
+        if template_use.arguments.iter().any(|argument| argument.contains('\n')) {
+            self.cursor = start;
+            return Err(ErrorKind::InvalidArguments);
+        }
 
+        let mut expanding = self.expanding.clone();
+        expanding.push(template.name.clone());
+        let mut parser = Parser {
+            depth: self.depth,
+            account_syntax: self.account_syntax,
+            file: self.file.clone(),
+            loader: self.loader.clone(),
+            includes: self.includes.clone(),
+            included: true,
+            templates: self.templates.clone(),
+            expanding,
+            ..Parser::new(&template.expand(&template_use.arguments))
+        };
 
-        if rangeint.is_numeric() {
-            from.push(self.next().unwrap());
+        // the errors are in the expanded text, so they're shown at the `use` line
+        let mut document = if self.recovering {
+            let (document, errors) = parser.parse_document_recovering();
+            for e in errors {
+                let err = self.template_error(e, start, &template, &template_use);
+                self.errors.push(err);
+            }
+            document
         } else {
-            // TODO: Should this be an error?
-            return Ok(None);
-        }
+            match parser.parse_document() {
+                Ok(document) => document,
+                Err(e) => {
+                    let kind = e.kind;
+                    let err = self.template_error(e, start, &template, &template_use);
+                    self.errors.push(err);
+                    return Err(kind);
+                }
+            }
+        };
 
-        while self.peek(1).unwrap().is_numeric() {
-            from.push(self.next().unwrap());
+        let mut span = match document.items.pop() {
+            Some(Item::Span(span)) if document.items.is_empty() => span,
+            _ => {
+                self.cursor = start;
+                return Err(ErrorKind::InvalidArguments);
+            }
+        };
+
+        span.set_location(location);
+        span.comments = comments;
+        span.template_use = Some(template_use);
+        if sub {
+            span.sum_type.make_sub_total();
         }
-        Ok(Some(from))
+
+        Ok(Some(span))
     }
 
-    fn next(&mut self) -> Option<char> {
-        let c = self.input.get(self.cursor).map(|c| *c);
-        self.cursor += 1;
-        c
+    /// An error in the block a template expands to, reported at the `use` line starting at
+    /// `start` with the template and the argument the error is in as its `note`.
+    fn template_error(&mut self, e: ParseError, start: usize, template: &Template, template_use: &TemplateUse) -> ParseError {
+        let mut note = format!("in template {}", template.name);
+        if let Some(i) = template.argument_at(&template_use.arguments, e.offset()) {
+            note.push_str(&format!(" with argument {} = {:?}", template.parameters[i], template_use.arguments[i]));
+        }
+        // an error in a template used in this one
+        if let Some(inner) = e.note {
+            note = format!("{}, {}", inner, note);
+        }
+
+        let cursor = self.cursor;
+        self.cursor = start;
+        let err = ParseError { note: Some(note), ..self.report_err(e.kind) };
+        self.cursor = cursor;
+        err
     }
 
-    fn peek(&self, n: usize) -> Option<char> {
-        self.input.get(self.cursor + n - 1).map(|c| *c)
+    /// use name(argument (, argument)*) comment? \n
+    /// Reads a `use` line without expanding it.
+    fn use_line(&mut self) -> Result<Option<UseLine>, AppErr> {
+        self.attributes()?;
+        if !self.at_use() {
+            return Ok(None);
+        }
+
+        let start = self.cursor;
+        if !self.attributes.is_empty() {
+            // the attributes of the block are written in the template
+            self.attributes.clear();
+            return Err(ErrorKind::DanglingAttributes);
+        }
+
+        self.cursor += USE.len();
+        self.skip_ws();
+        let name = self.name()?;
+        let arguments = self.list(Parser::argument)?;
+        let location = self.location(start, self.cursor);
+        let comment = self.end_of_line()?;
+
+        Ok(Some(UseLine { template_use: TemplateUse { name, arguments, comment }, start, location }))
     }
 
-    fn report_err(&self, msg: &str) -> String {
-        let (line, charpos, line_start_pos) = self
-        .input.iter()
-        .take(self.cursor)
-        .fold((0, 0, 0), |acc, ch| {
-            if *ch == '\n' {
-                let nl_pos = acc.2 + acc.1 + 1;
-                (acc.0 + 1, 0, nl_pos)
-            } else {
-                (acc.0, acc.1 + 1, acc.2)
-            }
-        });
+    fn at_use(&self) -> bool {
+        is_use(self.input[self.cursor.min(self.input.len())..].iter().copied())
+    }
 
-        //println!("line: {}, charpos: {}, lsp: {}", line, charpos, line_start_pos);
+    /// ( (item (, item)*)? )
+    /// The parameters of a template or the arguments of a `use` line.
+    fn list(&mut self, item: fn(&mut Parser) -> Result<String, AppErr>) -> Result<Vec<String>, AppErr> {
+        // at_template and at_use have seen the `(`
+        let _ = self.next();
+        let mut items = vec![];
 
-        let mut text = String::new();
-        let mut indicator = String::new();
+        self.skip_ws();
+        if self.peek(1) == Some(')') {
+            let _ = self.next();
+            return Ok(items);
+        }
 
-        for (i, ch) in self.input[line_start_pos..].iter().enumerate() {
-            match *ch {
-                '\n' => break,
-                _ => {
-                    text.push(*ch);
-                    let pos = line_start_pos + i;
-                    if pos < self.cursor {
-                        indicator.push('-');
-                    } else if pos == self.cursor {
-                        indicator.push('^');
-                    }
+        loop {
+            self.skip_ws();
+            items.push(item(self)?);
+            self.skip_ws();
+            match self.next() {
+                Some(',') => (),
+                Some(')') => return Ok(items),
+                Some(_) => {
+                    self.cursor -= 1;
+                    return Err(ErrorKind::UnexpectedSyntax);
                 }
+                None => return Err(ErrorKind::UnexpectedEof),
             }
         }
-        // we add 1 to line and charpos to show the place where the erronous syntax actually is
-        format!("\nline: {}, pos: {}\n{}\n{}\n\nERROR: {}\n", line + 1, charpos + 1, text, indicator, msg)
     }
-}
 
-/// Represents a range like `3000..3050 => Sales`
-/// 
-/// All the members of this struct is public so you can access the data directly.
-#[derive(Debug)]
-pub struct Range {
-    pub title: String,
-    pub from: u32,
-    pub to: u32,
+    /// "chars" | chars
+    /// An argument of a `use` line, it has to be in quotes unless it's a single word or number.
+    fn argument(&mut self) -> Result<String, AppErr> {
+        if self.peek(1) == Some('"') {
+            return self.quoted();
+        }
+
+        let argument = self.attribute_token(is_bare_argument_char);
+        if argument.is_empty() {
+            return Err(ErrorKind::UnexpectedSyntax);
+        }
+        Ok(argument)
+    }
+
+    /// report kind { comment? \n
+    /// Returns the report without its items and where the line starts.
+    fn report_start(&mut self, nested: bool) -> Result<Option<Section>, AppErr> {
+        self.attributes()?;
+        if !self.at_report() {
+            return Ok(None);
+        }
+
+        let start = self.cursor;
+        if !self.attributes.is_empty() {
+            // attributes belong to a line of the report
+            self.attributes.clear();
+            return Err(ErrorKind::DanglingAttributes);
+        }
+        if nested {
+            return Err(ErrorKind::NestedReport);
+        }
+        if self.in_block {
+            return Err(ErrorKind::ExpectedBlock);
+        }
+
+        self.cursor += REPORT.len();
+        self.skip_ws();
+        let kind_start = self.cursor;
+        let kind = match self.attribute_token(is_name_char).as_str() {
+            "balance" => ReportKind::Balance,
+            "result" => ReportKind::Result,
+            "opening" => ReportKind::Opening,
+            _ => {
+                self.cursor = kind_start;
+                return Err(ErrorKind::InvalidReportKind);
+            }
+        };
+
+        // at_report has seen the `{`
+        self.skip_ws();
+        let _ = self.next();
+        let location = self.location(start, self.cursor);
+        let comment = self.end_of_line()?;
+        let comments = std::mem::take(&mut self.comments);
+
+        let report = Report {
+            kind,
+            items: vec![],
+            location,
+            comments,
+            comment,
+            end_comments: vec![],
+            end_comment: None,
+        };
+        Ok(Some(Section { report, start }))
+    }
+
+    fn at_report(&self) -> bool {
+        is_report(self.input[self.cursor.min(self.input.len())..].iter().copied())
+    }
+
+    /// Gives the spans of the report its kind, except the ones from an included file with reports
+    /// of its own. The comments before the `}` are the end comments of the report.
+    fn close_report(&mut self, report: &mut Report) {
+        for span in all_spans_mut(&mut report.items).into_iter().filter(|span| span.report.is_none()) {
+            span.report = Some(report.kind);
+        }
+        report.end_comments = std::mem::take(&mut self.comments);
+    }
+
+    /// Records the error and skips to the next line if we're recovering from errors, returns the
+    /// error otherwise.
+    fn recover(&mut self, e: AppErr) -> Result<(), AppErr> {
+        if !self.recovering {
+            return Err(e);
+        }
+
+        let err = self.report_err(e);
+        self.errors.push(err);
+        self.skip_line();
+        Ok(())
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// ) => *char \n
+    fn block_end(&mut self) -> Result<BlockEnd, AppErr> {
+        let mut comment = None;
+        let mut is_block_end = false;
+
+        self.skip_ws_and_nl();
+        let start = self.cursor;
+        match self.peek(1) {
+            Some(')') => {
+                let _ = self.next();
+            }
+            _ => return Err(ErrorKind::ExpectedCloseParen),
+        }
+
+        while let Some(ch) = self.peek(1) {
+            match ch {
+                ' ' => {
+                    let _ = self.next();
+                }
+                '=' => {
+                    let _ = self.next();
+                    match self.peek(1) {
+                        Some('>') => {
+                            let _ = self.next();
+                            is_block_end = true;
+                            break;
+                        }
+
+                        Some(_) => return Err(ErrorKind::ExpectedArrow),
+                        _ => return Err(ErrorKind::ExpectedArrowAfterParen),
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        if !is_block_end {
+            let location = self.location(start, self.cursor);
+            if self.at_comment() {
+                comment = Some(self.comment());
+            }
+            return Ok(BlockEnd { name: None, translations: Translations::new(), location, comment })
+        }
+
+        // We know that we have ) =>
+
+        // the label is the rest of the line, it can be empty
+        let mut end = self.cursor;
+        self.skip_ws();
+        let name = self.title(None, &mut end)?;
+        let translations = self.translations(None, &mut end)?;
+        let comment = self.end_of_line()?;
+
+        Ok(BlockEnd {
+            name: Some(name),
+            translations,
+            location: self.location(start, end),
+            comment,
+        })
+    }
+
+    /// chars*(
+    /// Returns an error if there is a parse error in a block.
+    /// The next is an Option which indicates if there is a "block start" or not
+    /// The last option is to indicate if there is a title/header for the block or not
+    fn block_start(&mut self) -> Result<Option<BlockStart>, AppErr> {
+        let mut comment = None;
+
+        // if we got all the way to the end without finding a `(` we know this is not a block
+        // but it's not an error
+        if !self.at_block_start() {
+            return Ok(None);
+        }
+
+        self.skip_ws_and_nl();
+        let start = self.cursor;
+
+        // -Sales (
+        let reverse_sign = self.peek(1) == Some('-');
+        if reverse_sign {
+            let _ = self.next();
+        }
+
+        // "Depreciation (machinery)" (
+        self.skip_ws();
+        let name = if self.peek(1) == Some('"') {
+            Some(self.quoted()?)
+        } else {
+            let mut name = String::new();
+            while let Some(c) = self.peek(1) {
+                if c == '(' || self.at_translation() {
+                    break;
+                }
+
+                if self.at_comment() {
+                    comment = Some(self.comment());
+                    continue;
+                }
+                name.push(c);
+                let _ = self.next();
+            }
+
+            let name = name.trim().to_string();
+            if name.is_empty() { None } else { Some(name) }
+        };
+
+        // Salg | en: Sales (
+        let mut end = self.cursor;
+        let translations = self.translations(Some('('), &mut end)?;
+
+        self.skip_ws();
+        if self.peek(1) != Some('(') {
+            return Err(ErrorKind::UnexpectedSyntax);
+        }
+        let _ = self.next();
+        let location = self.location(start, self.cursor);
+
+        // Sales ( // comment
+        self.skip_ws();
+        if self.at_comment() {
+            comment = Some(self.comment());
+        }
+
+        Ok(Some(BlockStart {
+            name,
+            translations,
+            reverse_sign,
+            location,
+            comment,
+        }))
+    }
+
+    /// Looks ahead for the `(` of a block start. We know it's not one if we find a `)` or `=>`
+    /// first, or a `=` on a line that doesn't end with the `(`.
+    fn at_block_start(&self) -> bool {
+        let mut lookahed = 1;
+        let mut leading = true;
+        while let Some(c) = self.peek(lookahed) {
+            match c {
+                '(' => return true,
+
+                ')' => {
+                    return false
+                },
+
+                // `Revenue = net (` is a header, `Gross profit = Sum sales` a formula
+                '=' => {
+                    return self.peek(lookahed + 1) != Some('>') && self.ends_with_paren(lookahed)
+                },
+
+                // a header in quotes can contain anything
+                '"' if leading => {
+                    if let Some(end) = self.closing_quote(lookahed) {
+                        lookahed = end;
+                    }
+                },
+
+                // so can a translation like `| en: "Sales (net)"`
+                '|' => {
+                    if let Some(len) = self.translation_len(lookahed) {
+                        lookahed += len;
+                        leading = true;
+                        continue;
+                    }
+                },
+
+                // a comment can contain anything so we skip to the end of the line
+                '#' | '/' if self.comment_at(lookahed) => {
+                    while let Some(c) = self.peek(lookahed + 1) {
+                        if c == '\n' {
+                            break;
+                        }
+                        lookahed += 1;
+                    }
+                },
+
+                _ => (),
+            }
+
+            leading = leading && (c.is_whitespace() || c == '-');
+            lookahed += 1;
+        }
+
+        false
+    }
+
+    /// Reads the attribute lines like `#[hide_if_zero, note = 7]` before a range, block or
+    /// formula. The attributes are kept until they can be attached to the line after them. An
+    /// attribute line with an error is skipped if we're recovering from errors.
+    fn attributes(&mut self) -> Result<(), AppErr> {
+        loop {
+            match self.attribute_lines() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    // the attributes before the error don't belong to anything either
+                    self.attributes.clear();
+                    self.recover(e)?;
+                }
+            }
+        }
+    }
+
+    /// (#[ attribute (, attribute)* ] comment? \n)*
+    fn attribute_lines(&mut self) -> Result<(), AppErr> {
+        let mut last = None;
+
+        self.skip_ws_and_nl();
+        while self.at_attributes() {
+            last = Some(self.cursor);
+            self.cursor += 2;
+
+            loop {
+                self.attribute()?;
+                self.skip_ws();
+                match self.next() {
+                    Some(',') => (),
+                    Some(']') => break,
+                    _ => {
+                        self.cursor -= 1;
+                        return Err(ErrorKind::InvalidAttribute);
+                    }
+                }
+            }
+
+            // a comment can follow on the same line, but nothing else
+            self.skip_ws();
+            if !matches!(self.peek(1), Some('\n') | None) && !self.at_comment() {
+                return Err(ErrorKind::UnexpectedSyntax);
+            }
+            self.skip_ws_and_nl();
+        }
+
+        // the attributes belong to the line after them, there has to be one
+        if let Some(last) = last {
+            if matches!(self.peek(1), Some(')') | Some('}') | None) {
+                self.cursor = last;
+                return Err(ErrorKind::DanglingAttributes);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// key (= (value | "chars"))?
+    /// A key without a value is a flag like `hide_if_zero` and gets the value `true`.
+    fn attribute(&mut self) -> Result<(), AppErr> {
+        self.skip_ws();
+        let start = self.cursor;
+        let key = self.attribute_token(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if key.is_empty() {
+            return Err(ErrorKind::InvalidAttribute);
+        }
+
+        self.skip_ws();
+        let value = if self.peek(1) == Some('=') {
+            let _ = self.next();
+            self.skip_ws();
+            if self.peek(1) == Some('"') {
+                self.quoted()?
+            } else {
+                let value = self.attribute_token(is_bare_attribute_char);
+                if value.is_empty() {
+                    return Err(ErrorKind::InvalidAttribute);
+                }
+                value
+            }
+        } else {
+            "true".to_string()
+        };
+
+        if self.attributes.contains_key(&key) {
+            self.cursor = start;
+            return Err(ErrorKind::DuplicateAttribute);
+        }
+        self.attributes.insert(key, value);
+
+        Ok(())
+    }
+
+    fn attribute_token(&mut self, accepted: fn(char) -> bool) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.peek(1) {
+            if !accepted(c) {
+                break;
+            }
+            token.push(c);
+            let _ = self.next();
+        }
+        token
+    }
+
+    fn at_attributes(&self) -> bool {
+        self.peek(1) == Some('#') && self.peek(2) == Some('[')
+    }
+
+    /// " chars "
+    /// A string in double quotes where `\"`, `\\`, `\n` and `\t` are escapes. The cursor is at the
+    /// opening quote.
+    fn quoted(&mut self) -> Result<String, AppErr> {
+        let start = self.cursor;
+        let _ = self.next();
+
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    _ => {
+                        // point at the `\`
+                        self.cursor -= 2;
+                        return Err(ErrorKind::InvalidEscape);
+                    }
+                },
+                Some('\n') | None => {
+                    self.cursor = start;
+                    return Err(ErrorKind::UnclosedString);
+                }
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// "chars" | chars
+    /// A title in quotes, or up to the end of the line, a comment or a translation. Headers and
+    /// formula titles also end at the `(` or `=` after them. `end` is moved past the last
+    /// character of the title that isn't whitespace.
+    fn title(&mut self, until: Option<char>, end: &mut usize) -> Result<String, AppErr> {
+        if self.peek(1) == Some('"') {
+            let title = self.quoted()?;
+            *end = self.cursor;
+            return Ok(title);
+        }
+
+        let mut title = String::new();
+        while let Some(c) = self.peek(1) {
+            if c == '\n' || Some(c) == until || self.at_comment() || self.at_translation() {
+                break;
+            }
+
+            title.push(c);
+            let _ = self.next();
+            if !c.is_whitespace() {
+                *end = self.cursor;
+            }
+        }
+
+        // remove any trailing spaces
+        Ok(title.trim_end().to_string())
+    }
+
+    /// (| locale: title)*
+    /// The translations of a title like `Salg | en: Sales | de: Umsatz`.
+    fn translations(&mut self, until: Option<char>, end: &mut usize) -> Result<Translations, AppErr> {
+        let mut translations = Translations::new();
+        loop {
+            self.skip_ws();
+            let len = match self.translation_len(1) {
+                Some(len) => len,
+                None => return Ok(translations),
+            };
+
+            // the locale is between the `|` and the `:`
+            let start = self.cursor;
+            let locale: String = self.input[start + 1..start + len - 1].iter().collect();
+            let locale = locale.trim().to_string();
+            self.cursor += len;
+
+            self.skip_ws();
+            let title = self.title(until, end)?;
+            if translations.contains_key(&locale) {
+                self.cursor = start;
+                return Err(ErrorKind::DuplicateTranslation);
+            }
+            translations.insert(locale, title);
+        }
+    }
+
+    fn at_translation(&self) -> bool {
+        self.translation_len(1).is_some()
+    }
+
+    /// Returns the length of the `| locale:` at `lookahead` if there is one.
+    fn translation_len(&self, lookahead: usize) -> Option<usize> {
+        let rest = self.input.get(self.cursor + lookahead - 1..)?;
+        translation_start(rest.iter().copied())
+    }
+
+    /// Returns the lookahead of the `"` that closes the string starting at `lookahead`, or `None`
+    /// if it's not closed on the same line.
+    fn closing_quote(&self, lookahead: usize) -> Option<usize> {
+        let mut lookahead = lookahead + 1;
+        while let Some(c) = self.peek(lookahead) {
+            match c {
+                '"' => return Some(lookahead),
+                '\\' => lookahead += 1,
+                '\n' => return None,
+                _ => (),
+            }
+            lookahead += 1;
+        }
+
+        None
+    }
+
+    /// Reads what is left of the line after a quoted title. Only a comment can follow it. The
+    /// line break is consumed.
+    fn end_of_line(&mut self) -> Result<Option<String>, AppErr> {
+        self.skip_ws();
+        let comment = if self.at_comment() {
+            Some(self.comment())
+        } else {
+            None
+        };
+
+        match self.peek(1) {
+            Some('\n') => {
+                let _ = self.next();
+                Ok(comment)
+            }
+            None => Ok(comment),
+            Some(_) => Err(ErrorKind::UnexpectedSyntax),
+        }
+    }
+
+    fn at_comment(&self) -> bool {
+        self.comment_at(1)
+    }
+
+    /// True if a comment starts at `lookahead`. A `#` only starts a comment at the start of a
+    /// line or with whitespace on both sides of it, so a title like `Account #5` can have one in
+    /// it.
+    fn comment_at(&self, lookahead: usize) -> bool {
+        match self.peek(lookahead) {
+            Some('#') => {
+                let before = self.input[..self.cursor + lookahead - 1].iter().rev();
+                match before.clone().next() {
+                    None | Some('\n') => true,
+                    Some(c) if c.is_whitespace() => {
+                        matches!(self.peek(lookahead + 1), None | Some(' ' | '\t' | '\r' | '\n'))
+                            || before.take_while(|c| **c != '\n').all(|c| c.is_whitespace())
+                    }
+                    Some(_) => false,
+                }
+            }
+            Some('/') => self.peek(lookahead + 1) == Some('/'),
+            _ => false,
+        }
+    }
+
+    /// Reads a comment starting at the cursor to the end of the line. The line break is not
+    /// consumed.
+    fn comment(&mut self) -> String {
+        if self.next() == Some('/') {
+            let _ = self.next();
+        }
+
+        let mut text = String::new();
+        while let Some(c) = self.peek(1) {
+            if c == '\n' {
+                break;
+            }
+            text.push(c);
+            let _ = self.next();
+        }
+
+        text.trim().to_string()
+    }
+
+    fn is_space_or_newline(c: char) -> bool {
+        c.is_whitespace() || c.is_control()
+    }
+
+    /// Skips whitespace on the current line.
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek(1) {
+            if c.is_whitespace() && c != '\n' {
+                let _ = self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skips whitespace, line breaks and comments. The comments are kept until they can be
+    /// attached to the next node we parse. It stops at attributes since `#[` isn't a comment.
+    fn skip_ws_and_nl(&mut self) {
+        while let Some(c) = self.peek(1) {
+            if Parser::is_space_or_newline(c) {
+                let _ = self.next();
+            } else if self.at_comment() && !self.at_attributes() {
+                let comment = self.comment();
+                self.comments.push(comment);
+            } else {
+                break;
+            }
+        }
+    }
+    /// -? accounts (, accounts)* ' '* => ' '* char* /n
+    fn range(&mut self) -> Result<Option<Range>, AppErr> {
+        // #[hide_if_zero]
+        self.attributes()?;
+        let start = self.cursor;
+
+        // a `-` in front reverses the sign, `-(` is the start of a block with the sign reversed
+        let reverse_sign = self.peek(1) == Some('-');
+        let first = if reverse_sign { self.peek(2) } else { self.peek(1) };
+        let is_range = match first {
+            Some(c) if c == '!' || c == '@' || c.is_ascii_digit() => true,
+            // `{base}000..{base}499 => Sales` in the body of a template
+            Some('{') if self.parameters.is_some() => !self.at_block_start(),
+            // an account can start with a letter, but so can the title of a block like `Sales (`
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.account_syntax == AccountSyntax::Alphanumeric && !self.at_block_start() && !self.at_include()
+            }
+            _ => false,
+        };
+        if !is_range {
+            return Ok(None);
+        }
+        if reverse_sign {
+            let _ = self.next();
+        }
+
+        // 1111..2222, 3333, !4444, @bank
+        let mut sets = vec![];
+        let accounts = self.account_list(Some(&mut sets))?;
+
+        // the accounts of a range set aren't known until it's resolved
+        if sets.is_empty() && accounts.iter().all(|accounts| accounts.excluded) {
+            self.cursor = start;
+            return Err(ErrorKind::OnlyExclusions);
+        }
+
+        // =>
+        self.skip_ws();
+        if let Some(c) = self.peek(1) {
+            match c {
+                '=' => {
+                    let _ = self.next();
+                    match self.peek(1) {
+                        Some('>') => {
+                            let _ = self.next();
+                        }
+                        Some(_) => {
+                            return Err(ErrorKind::InvalidSyntaxAfterEq);
+                        },
+                        None => return Err(ErrorKind::UnexpectedEof),
+                    }
+                }
+
+                // reported at the end of the range rather than on the next line
+                _ => return Err(ErrorKind::ExpectedArrow),
+            }
+        }
+
+        // Title
+        let mut end = self.cursor;
+        self.skip_ws();
+
+        // => Salg | en: Sales
+        let title = self.title(None, &mut end)?;
+        let translations = self.translations(None, &mut end)?;
+        let comment = self.end_of_line()?;
+
+        let location = self.location(start, end);
+        let comments = std::mem::take(&mut self.comments);
+        let attributes = std::mem::take(&mut self.attributes);
+        let range = Range { title, translations, accounts, sets, reverse_sign, location, comments, attributes, comment };
+
+        Ok(Some(range))
+    }
+
+    /// accounts (, accounts)*
+    /// The account list of a range line or range set. The range sets like `@bank` are added to
+    /// `sets` if they're allowed.
+    fn account_list(&mut self, mut sets: Option<&mut Vec<SetReference>>) -> Result<Vec<AccountRange>, AppErr> {
+        let mut accounts = vec![];
+        loop {
+            match sets.as_deref_mut() {
+                Some(sets) if self.peek(1) == Some('@') => {
+                    let _ = self.next();
+                    let name = self.name()?;
+                    sets.push(SetReference { name, start: accounts.len(), len: 0 });
+                }
+                _ => accounts.push(self.accounts()?),
+            }
+
+            self.skip_ws();
+            if self.peek(1) != Some(',') {
+                break;
+            }
+            let _ = self.next();
+            self.skip_ws();
+        }
+
+        Ok(accounts)
+    }
+
+    /// !? account ((.. account) | \*)?
+    fn accounts(&mut self) -> Result<AccountRange, AppErr> {
+        let excluded = self.peek(1) == Some('!');
+        if excluded {
+            let _ = self.next();
+        }
+
+        // 1111
+        let from = match self.check_range_part()? {
+            Some(range) => range,
+            None => return Err(ErrorKind::InvalidRange),
+        };
+
+        // a pattern like `30xx` or `3*`
+        if let Some(pattern) = self.pattern(&from)? {
+            let (from, to) = match &pattern {
+                AccountPattern::Digits { prefix, wildcards } => {
+                    let digits = |digit: &str| digit.repeat(*wildcards as usize);
+                    (format!("{}{}", prefix, digits("0")), format!("{}{}", prefix, digits("9")))
+                }
+                AccountPattern::Prefix { prefix } => (prefix.clone(), prefix.clone()),
+            };
+            let (from, to) = (AccountId::new(from), AccountId::new(to));
+            return Ok(AccountRange { from, to, single_account: false, excluded, pattern: Some(pattern) });
+        }
+
+        // a single account like `3010 => Webshop`, or at the end of a `let` line
+        let single_account = self.peek(1).is_none()
+            || self.at_comment()
+            || matches!(self.peek(1), Some(c) if c == '=' || c == ',' || c.is_whitespace());
+
+        let to = if single_account {
+            from.clone()
+        } else {
+            // ..
+            for _ in 0..2 {
+                match self.next() {
+                    Some('.') => (),
+                    Some(_) => {
+                        // we need to decrease the cursor since we already moved past the error
+                        self.cursor -= 1;
+                        return Err(ErrorKind::InvalidRangeSyntax);
+                    },
+                    None => return Err(ErrorKind::UnexpectedEof),
+                }
+            }
+
+            // 1111
+            match self.check_range_part()? {
+                Some(range) => range,
+                None => return Err(ErrorKind::InvalidRange),
+            }
+        };
+
+        Ok(AccountRange { from, to, single_account, excluded, pattern: None })
+    }
+
+    /// x* | \*
+    /// `account` is the account we just read. A trailing run of `x` after a digit makes it a
+    /// pattern like `30xx`, and a `*` after it a pattern like `3*`.
+    fn pattern(&mut self, account: &AccountId) -> Result<Option<AccountPattern>, AppErr> {
+        let text = account.as_str();
+        let prefix = text.trim_end_matches(['x', 'X']);
+        // the `x`s are only a part of the account we read if it can have letters
+        let mut wildcards = text.len() - prefix.len();
+        while let Some('x') | Some('X') = self.peek(1) {
+            let _ = self.next();
+            wildcards += 1;
+        }
+
+        let pattern = if self.peek(1) == Some('*') && wildcards == 0 {
+            let _ = self.next();
+            AccountPattern::Prefix { prefix: prefix.to_string() }
+        } else if wildcards > 0 && prefix.ends_with(|c: char| c.is_ascii_digit()) {
+            AccountPattern::Digits { prefix: prefix.to_string(), wildcards: wildcards as u32 }
+        } else {
+            return Ok(None);
+        };
+
+        // nothing can follow the wildcards, `3*0` or `30xx*` aren't patterns
+        if matches!(self.peek(1), Some(c) if c == '*' || AccountId::is_account_char(c)) {
+            return Err(ErrorKind::InvalidPattern);
+        }
+
+        Ok(Some(pattern))
+    }
+
+    /// Reads an account identifier like `3000`, or `1920.01` and `A-4000` if they're allowed. A
+    /// `..` ends it since that's the start of the end of a range.
+    fn check_range_part(&mut self) -> Result<Option<AccountId>, AppErr> {
+        let start = self.cursor;
+        let mut account = String::new();
+
+        while let Some(c) = self.peek(1) {
+            // a parameter like `{base}000` in the body of a template, it's read as a 0
+            if c == '{' && self.parameters.is_some() {
+                let open = self.cursor;
+                let _ = self.next();
+                let name = self.attribute_token(is_name_char);
+                let known = self.parameters.iter().flatten().any(|parameter| *parameter == name);
+                if self.next() != Some('}') || !known {
+                    self.cursor = open;
+                    return Err(ErrorKind::UnknownParameter);
+                }
+                account.push('0');
+                continue;
+            }
+
+            let accepted = match self.account_syntax {
+                AccountSyntax::Digits => c.is_ascii_digit(),
+                AccountSyntax::Alphanumeric => AccountId::is_account_char(c),
+            };
+            if !accepted || (c == '.' && self.peek(2) == Some('.')) {
+                break;
+            }
+            account.push(c);
+            let _ = self.next();
+        }
+
+        if account.is_empty() {
+            // TODO: Should this be an error?
+            return Ok(None);
+        }
+
+        match account.parse() {
+            Ok(account) => Ok(Some(account)),
+            Err(_) => {
+                // point at the start of the account, not the end
+                self.cursor = start;
+                Err(ErrorKind::InvalidAccount)
+            }
+        }
+    }
+
+    /// title = expr ' '* comment? \n
+    /// A computed line like `Gross profit = Sum sales - Sum material`.
+    fn formula(&mut self) -> Result<Option<Formula>, AppErr> {
+        self.skip_ws_and_nl();
+        if !self.at_formula() {
+            return Ok(None);
+        }
+
+        let start = self.cursor;
+        // "Result (after tax)" = ...
+        let mut end = self.cursor;
+        let title = self.title(Some('='), &mut end)?;
+        if title.is_empty() {
+            self.cursor = start;
+            return Err(ErrorKind::MissingFormulaTitle);
+        }
+
+        // Bruttofortjeneste | en: Gross profit = ...
+        let translations = self.translations(Some('='), &mut end)?;
+        self.skip_ws();
+        if self.peek(1) != Some('=') {
+            return Err(ErrorKind::UnexpectedSyntax);
+        }
+        let _ = self.next();
+
+        // the depth counts the operators and parentheses of the formula so the tree can't get
+        // deep enough to overflow the stack
+        let depth = self.depth;
+        let expr = self.expr();
+        self.depth = depth;
+        let expr = expr?;
+
+        let mut end = self.cursor;
+        while end > start && self.input[end - 1].is_whitespace() {
+            end -= 1;
+        }
+
+        self.skip_ws();
+        let comment = if self.at_comment() {
+            Some(self.comment())
+        } else {
+            None
+        };
+
+        match self.peek(1) {
+            Some('\n') | None => (),
+            Some(_) => return Err(ErrorKind::UnexpectedSyntax),
+        }
+
+        let location = self.location(start, end);
+        let comments = std::mem::take(&mut self.comments);
+        let attributes = std::mem::take(&mut self.attributes);
+        Ok(Some(Formula { title, translations, expr, location, comments, attributes, comment }))
+    }
+
+    /// Looks ahead for the `=` of a formula on the current line. The `=>` of a range line doesn't
+    /// count, and a `(` first or at the end of the line means it's the header of a block.
+    fn at_formula(&self) -> bool {
+        let mut lookahead = 1;
+        let mut leading = true;
+        while let Some(c) = self.peek(lookahead) {
+            match c {
+                '=' => return self.peek(lookahead + 1) != Some('>') && !self.ends_with_paren(lookahead),
+                '\n' | '(' | ')' => return false,
+                '#' | '/' if self.comment_at(lookahead) => return false,
+
+                // a title in quotes can contain anything
+                '"' if leading => {
+                    if let Some(end) = self.closing_quote(lookahead) {
+                        lookahead = end;
+                    }
+                }
+
+                // so can a translation like `| en: "Result = x"`
+                '|' => {
+                    if let Some(len) = self.translation_len(lookahead) {
+                        lookahead += len;
+                        leading = true;
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            leading = leading && c.is_whitespace();
+            lookahead += 1;
+        }
+
+        false
+    }
+
+    /// True if the last thing on the line from `lookahead`, not counting a comment, is a `(`.
+    fn ends_with_paren(&self, mut lookahead: usize) -> bool {
+        let mut last = None;
+        while let Some(c) = self.peek(lookahead) {
+            if c == '\n' || self.comment_at(lookahead) {
+                break;
+            }
+            if !c.is_whitespace() {
+                last = Some(c);
+            }
+            lookahead += 1;
+        }
+
+        last == Some('(')
+    }
+
+    /// term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, AppErr> {
+        let mut left = self.term()?;
+        loop {
+            self.skip_ws();
+            let operator = match self.peek(1) {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Subtract,
+                _ => break,
+            };
+            self.deeper()?;
+            let _ = self.next();
+
+            let right = self.term()?;
+            left = Expr::Binary { operator, left: Box::new(left), right: Box::new(right) };
+        }
+
+        Ok(left)
+    }
+
+    /// factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr, AppErr> {
+        let mut left = self.factor()?;
+        loop {
+            self.skip_ws();
+            let operator = match self.peek(1) {
+                Some('*') => Operator::Multiply,
+                // `//` starts a comment
+                Some('/') if !self.at_comment() => Operator::Divide,
+                _ => break,
+            };
+            self.deeper()?;
+            let _ = self.next();
+
+            let right = self.factor()?;
+            left = Expr::Binary { operator, left: Box::new(left), right: Box::new(right) };
+        }
+
+        Ok(left)
+    }
+
+    /// '-' factor | '(' expr ')' | '[' chars ']' | '"' chars '"' | number | reference
+    fn factor(&mut self) -> Result<Expr, AppErr> {
+        self.skip_ws();
+        match self.peek(1) {
+            Some('-') => {
+                self.deeper()?;
+                let _ = self.next();
+                let operand = self.factor()?;
+                Ok(Expr::Negate { operand: Box::new(operand) })
+            }
+
+            Some('(') => {
+                self.deeper()?;
+                let _ = self.next();
+                let expr = self.expr()?;
+                self.skip_ws();
+                if self.peek(1) != Some(')') {
+                    return Err(ErrorKind::ExpectedCloseParen);
+                }
+                let _ = self.next();
+                Ok(expr)
+            }
+
+            // a reference in brackets can contain operators, like `[Sum non-operating costs]`
+            Some('[') => {
+                let start = self.cursor;
+                let _ = self.next();
+                let mut name = String::new();
+                loop {
+                    match self.next() {
+                        Some(']') => break,
+                        Some('\n') | None => {
+                            self.cursor = start;
+                            return Err(ErrorKind::UnclosedReference);
+                        }
+                        Some(c) => name.push(c),
+                    }
+                }
+
+                let name = name.trim().to_string();
+                if name.is_empty() {
+                    self.cursor = start;
+                    return Err(ErrorKind::ExpectedOperand);
+                }
+                Ok(Expr::Reference { name })
+            }
+
+            // "Result (after tax)"
+            Some('"') => {
+                let start = self.cursor;
+                let name = self.quoted()?;
+                if name.is_empty() {
+                    self.cursor = start;
+                    return Err(ErrorKind::ExpectedOperand);
+                }
+                Ok(Expr::Reference { name })
+            }
+
+            _ => {
+                let start = self.cursor;
+                let mut text = String::new();
+                while let Some(c) = self.peek(1) {
+                    if c == '\n' || "+-*/()[]".contains(c) || self.at_comment() {
+                        break;
+                    }
+                    text.push(c);
+                    let _ = self.next();
+                }
+
+                let text = text.trim();
+                if text.is_empty() {
+                    self.cursor = start;
+                    return Err(ErrorKind::ExpectedOperand);
+                }
+
+                match text.parse() {
+                    Ok(value) => Ok(Expr::Number { value }),
+                    Err(_) => Ok(Expr::Reference { name: text.to_string() }),
+                }
+            }
+        }
+    }
+
+    fn deeper(&mut self) -> Result<(), AppErr> {
+        if self.depth >= MAX_NESTING {
+            return Err(ErrorKind::FormulaTooLong);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.input.get(self.cursor).copied();
+        self.cursor += 1;
+        c
+    }
+
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input.get(self.cursor + n - 1).copied()
+    }
+
+    /// Calculates the position of `cursor` in the input. Returns the position and the index of
+    /// the first character on that line.
+    fn position(&self, cursor: usize) -> (Position, usize) {
+        let cursor = cursor.min(self.input.len());
+        // the first line starts at 0, so there's always one before the cursor
+        let line = self.line_starts.partition_point(|(start, _)| *start <= cursor) - 1;
+        let (line_start_pos, line_offset) = self.line_starts[line];
+        let before = &self.input[line_start_pos..cursor];
+
+        // we add 1 to line and charpos to show the place where the erronous syntax actually is
+        let position = Position {
+            line: line + 1,
+            column: before.len() + 1,
+            offset: line_offset + before.iter().map(|ch| ch.len_utf8()).sum::<usize>(),
+        };
+
+        (position, line_start_pos)
+    }
+
+    fn location(&self, start: usize, end: usize) -> Location {
+        Location {
+            start: self.position(start).0,
+            end: self.position(end).0,
+        }
+    }
+
+    fn report_err(&mut self, kind: ErrorKind) -> ParseError {
+        let (position, line_start_pos) = self.position(self.cursor);
+
+        let source_line = self.input[line_start_pos..]
+            .iter()
+            .take_while(|ch| **ch != '\n')
+            .collect();
+
+        ParseError {
+            kind,
+            position,
+            source_line,
+            file: self.file.clone(),
+            note: self.note.take(),
+        }
+    }
+}
+
+/// The attributes of a line like `#[hide_if_zero, note = 7]` by their key. A key without a value
+/// like `hide_if_zero` has the value `"true"`. The parser doesn't give them any meaning, they're
+/// there for renderers and other tools.
+pub type Attributes = BTreeMap<String, String>;
+
+/// Returns true if `chars` starts with `include` and a quote, like `include "equity.qa"`.
+pub(crate) fn is_include(chars: impl Iterator<Item = char>) -> bool {
+    let mut chars = chars.peekable();
+    for c in INCLUDE.chars() {
+        if chars.next() != Some(c) {
+            return false;
+        }
+    }
+
+    let mut spaces = 0;
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {
+        spaces += 1;
+    }
+    spaces > 0 && chars.next() == Some('"')
+}
+
+/// Returns true if `chars` starts with a range set definition like `let bank =`.
+pub(crate) fn is_let(chars: impl Iterator<Item = char>) -> bool {
+    let mut chars = chars.peekable();
+    for c in LET.chars() {
+        if chars.next() != Some(c) {
+            return false;
+        }
+    }
+
+    let space = |c: &char| *c == ' ' || *c == '\t';
+    if chars.next_if(space).is_none() {
+        return false;
+    }
+    while chars.next_if(space).is_some() {}
+    if chars.next_if(|c| is_name_char(*c)).is_none() {
+        return false;
+    }
+    while chars.next_if(|c| is_name_char(*c)).is_some() {}
+    while chars.next_if(space).is_some() {}
+
+    // `let x => Sales` isn't a range set
+    chars.next() == Some('=') && chars.next() != Some('>')
+}
+
+/// Returns true if `chars` starts with a template definition like `template department(`.
+pub(crate) fn is_template(chars: impl Iterator<Item = char>) -> bool {
+    is_call(TEMPLATE, chars)
+}
+
+/// Returns true if `chars` starts with the use of a template like `use department(`.
+pub(crate) fn is_use(chars: impl Iterator<Item = char>) -> bool {
+    is_call(USE, chars)
+}
+
+/// The keyword and a name followed by `(`. There is no space before the `(`, so a block like
+/// `use of funds (` isn't a `use` line.
+fn is_call(keyword: &str, chars: impl Iterator<Item = char>) -> bool {
+    let mut chars = chars.peekable();
+    for c in keyword.chars() {
+        if chars.next() != Some(c) {
+            return false;
+        }
+    }
+
+    let space = |c: &char| *c == ' ' || *c == '\t';
+    if chars.next_if(space).is_none() {
+        return false;
+    }
+    while chars.next_if(space).is_some() {}
+    if chars.next_if(|c| is_name_char(*c)).is_none() {
+        return false;
+    }
+    while chars.next_if(|c| is_name_char(*c)).is_some() {}
+
+    chars.next() == Some('(')
+}
+
+/// Returns true if `chars` starts with a report like `report balance {`.
+pub(crate) fn is_report(chars: impl Iterator<Item = char>) -> bool {
+    let mut chars = chars.peekable();
+    for c in REPORT.chars() {
+        if chars.next() != Some(c) {
+            return false;
+        }
+    }
+
+    let space = |c: &char| *c == ' ' || *c == '\t';
+    if chars.next_if(space).is_none() {
+        return false;
+    }
+    while chars.next_if(space).is_some() {}
+    if chars.next_if(|c| is_name_char(*c)).is_none() {
+        return false;
+    }
+    while chars.next_if(|c| is_name_char(*c)).is_some() {}
+    while chars.next_if(space).is_some() {}
+
+    chars.next() == Some('{')
+}
+
+/// The characters in the name of a range set, template or parameter.
+pub(crate) fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// A value of an attribute can be written without quotes if it only has these characters, like
+/// `7`, `1.5` or `false`.
+pub(crate) fn is_bare_attribute_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// An argument of a `use` line can be written without quotes if it only has these characters,
+/// like `30`, `Oslo` or `{base}` in the body of a template.
+pub(crate) fn is_bare_argument_char(c: char) -> bool {
+    is_bare_attribute_char(c) || c == '{' || c == '}'
+}
+
+/// Represents a line of a span like `3000..3050 => Sales` or `3000..3999, !3010 => Other sales`.
+/// 
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Range {
+    pub title: String,
+    /// The title in other languages, like `Sales` in `3000 => Salg | en: Sales`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Translations::is_empty"))]
+    pub translations: Translations,
+    /// The accounts and exclusions in the order they're written, with the accounts of the range
+    /// sets in `sets` where they're used. At least one of them isn't excluded, unless the line
+    /// uses a range set.
+    pub accounts: Vec<AccountRange>,
+    /// The range sets like `@bank` used in the account list.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub sets: Vec<SetReference>,
+    /// True if the line is written with a `-` in front like `-3000..3999 => Sales`, the amount is
+    /// shown with the opposite sign of the balances.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub reverse_sign: bool,
+    /// Where in the source the range line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the range.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// The attributes like `#[hide_if_zero]` on the lines before the range.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Attributes::is_empty"))]
+    pub attributes: Attributes,
+    /// A comment after the title on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+}
+
+/// A named account list like `let bank = 1900..1999, !1950` that range lines can use as `@bank`.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RangeSet {
+    pub name: String,
+    /// The accounts and exclusions in the order they're written.
+    pub accounts: Vec<AccountRange>,
+    /// Where in the source the `let` line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the `let` line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// A comment after the account list on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+}
+
+/// A range set used in the account list of a range line like `@bank`. The accounts of the set are
+/// `accounts[start..start + len]` of the `Range` when the set is resolved, `len` is 0 before that
+/// and if there is no set with the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetReference {
+    pub name: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// A block written once with parameters, like `template department(title, base)` followed by the
+/// block, and used as `use department(Sales, 30)`. The parameters are written as `{title}` and
+/// `{base}` in the block.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Template {
+    pub name: String,
+    pub parameters: Vec<String>,
+    /// The block as it's written, with the `{parameter}`s in it.
+    pub body: String,
+    /// Where in the source the `template` line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the `template` line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// A comment after the parameters on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+}
+
+impl Template {
+    /// The text of the block with the arguments in place of the parameters. A `{name}` that isn't
+    /// a parameter, or a parameter without an argument, is kept as it is.
+    pub fn expand<S: AsRef<str>>(&self, arguments: &[S]) -> String {
+        self.expand_marked(arguments).0
+    }
+
+    /// The index of the argument at a byte offset in the text `expand` gives, or of the first
+    /// argument on the line if the offset isn't in one.
+    pub(crate) fn argument_at<S: AsRef<str>>(&self, arguments: &[S], offset: usize) -> Option<usize> {
+        let (text, places) = self.expand_marked(arguments);
+        let offset = offset.min(text.len());
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+
+        places
+            .iter()
+            .find(|(_, place)| place.start <= offset && offset <= place.end)
+            .or_else(|| places.iter().find(|(_, place)| line_start <= place.start && place.end <= line_end))
+            .map(|(i, _)| *i)
+    }
+
+    /// The expanded text with the index of every argument put in it and where it is in the text.
+    fn expand_marked<S: AsRef<str>>(&self, arguments: &[S]) -> (String, Vec<(usize, std::ops::Range<usize>)>) {
+        let mut text = String::new();
+        let mut places = vec![];
+        let mut rest = self.body.as_str();
+        while let Some(open) = rest.find('{') {
+            text.push_str(&rest[..open]);
+            rest = &rest[open..];
+
+            let parameter = rest[1..].find('}').map(|close| &rest[1..close + 1]);
+            let argument = parameter
+                .and_then(|parameter| self.parameters.iter().position(|p| p == parameter))
+                .and_then(|i| arguments.get(i).map(|argument| (i, argument)));
+            match (parameter, argument) {
+                (Some(parameter), Some((i, argument))) => {
+                    places.push((i, text.len()..text.len() + argument.as_ref().len()));
+                    text.push_str(argument.as_ref());
+                    rest = &rest[parameter.len() + 2..];
+                }
+                _ => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        (text, places)
+    }
+}
+
+/// A `use department(Sales, 30)` line, see `Template`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemplateUse {
+    pub name: String,
+    pub arguments: Vec<String>,
+    /// A comment after the `)` on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+}
+
+/// One entry in the account list of a range line: a range of accounts like `3000..3999`, a single
+/// account like `8100` or an exclusion like `!3010` or `!3500..3599`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccountRange {
+    pub from: AccountId,
+    pub to: AccountId,
+    /// True if this was written as a single account like `8100`. `from` and `to` are the same
+    /// account then.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub single_account: bool,
+    /// True for exclusions like `!3010`, the accounts are taken out of the line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub excluded: bool,
+    /// Set if this was written as a pattern like `30xx` or `3*`. `from` and `to` are the smallest
+    /// and largest account a `30xx` pattern matches and just the prefix of a `3*` pattern.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub pattern: Option<AccountPattern>,
+}
+
+/// An account pattern, matched against the account identifier exactly as it's written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum AccountPattern {
+    /// A pattern like `30xx` where every `x` stands for exactly one digit. It only matches
+    /// accounts with as many characters as the pattern, so `30xx` is 3000 to 3099 but not 300 or
+    /// 30000, and `03xx` is 0300 to 0399.
+    Digits { prefix: String, wildcards: u32 },
+    /// A pattern like `3*` that matches every account starting with the prefix, no matter how long
+    /// it is: 3, 30 to 39, 300 to 399, 3000.01 and so on.
+    Prefix { prefix: String },
+}
+
+impl AccountPattern {
+    /// Returns true if the pattern matches the account.
+    pub fn matches(&self, account: &AccountId) -> bool {
+        match self {
+            AccountPattern::Digits { prefix, wildcards } => match account.as_str().strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.len() == *wildcards as usize && rest.chars().all(|c| c.is_ascii_digit()),
+                None => false,
+            },
+            AccountPattern::Prefix { prefix } => account.as_str().starts_with(prefix.as_str()),
+        }
+    }
+
+    /// The accounts written as plain numbers the pattern matches, as sorted `(from, to)` pairs
+    /// with both ends included. `30xx` is a single pair and `3*` one pair for each number of
+    /// digits. A pattern with letters or a leading zero like `03xx` doesn't match any plain
+    /// numbers.
+    pub fn intervals(&self) -> Vec<(u32, u32)> {
+        match self {
+            AccountPattern::Digits { prefix, wildcards } => {
+                let scale = 10u32.checked_pow(*wildcards);
+                let interval = match (plain_number(prefix), scale) {
+                    // `xx` without a prefix is the numbers with two digits, and 0 to 9 for `x`
+                    (_, Some(scale)) if prefix.is_empty() && scale > 1 => Some((if scale == 10 { 0 } else { scale / 10 }, scale - 1)),
+                    // only 0 itself starts with 0
+                    (Some(0), _) => None,
+                    (Some(prefix), Some(scale)) => prefix
+                        .checked_mul(scale)
+                        .and_then(|from| Some((from, from.checked_add(scale - 1)?))),
+                    _ => None,
+                };
+                interval.into_iter().collect()
+            }
+            AccountPattern::Prefix { prefix } if prefix.is_empty() => vec![(0, u32::MAX)],
+            AccountPattern::Prefix { prefix } => match plain_number(prefix) {
+                // 0 has no digits after it
+                Some(0) => vec![(0, 0)],
+                Some(prefix) => {
+                    let mut intervals = vec![];
+                    let mut scale: u32 = 1;
+                    while let Some(from) = prefix.checked_mul(scale) {
+                        let to = (prefix + 1).checked_mul(scale).map_or(u32::MAX, |to| to - 1);
+                        intervals.push((from, to));
+                        scale = match scale.checked_mul(10) {
+                            Some(scale) => scale,
+                            None => break,
+                        };
+                    }
+                    intervals
+                }
+                None => vec![],
+            },
+        }
+    }
+}
+
+/// The number an account is if it's written as a plain number, `3000` but not `0150` or `A-4000`.
+fn plain_number(text: &str) -> Option<u32> {
+    let plain = !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) && (text == "0" || !text.starts_with('0'));
+    if plain {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+impl AccountRange {
+    /// Returns true if the account is captured by this entry, ignoring if it's excluded or not.
+    /// A reversed range doesn't capture anything.
+    pub fn contains(&self, account: &AccountId) -> bool {
+        match &self.pattern {
+            Some(pattern) => pattern.matches(account),
+            None => &self.from <= account && account <= &self.to,
+        }
+    }
+
+    /// The accounts written as plain numbers this entry captures as sorted `(from, to)` pairs with
+    /// both ends included, ignoring if it's excluded or not. That's just `from..to` for a range of
+    /// plain numbers. A reversed range doesn't capture anything.
+    pub fn intervals(&self) -> Vec<(u32, u32)> {
+        if let Some(pattern) = &self.pattern {
+            return pattern.intervals();
+        }
+
+        // the plain numbers between two accounts like `0150` and `A-4000` are 150 to u32::MAX
+        let from = match first_number(&self.from) {
+            Some(number) if AccountId::from(number) >= self.from => Some(number),
+            Some(number) => number.checked_add(1),
+            // a letter comes after all numbers
+            None => None,
+        };
+        let to = match first_number(&self.to) {
+            Some(number) if AccountId::from(number) <= self.to => Some(number),
+            Some(number) => number.checked_sub(1),
+            None => Some(u32::MAX),
+        };
+
+        match (from, to) {
+            (Some(from), Some(to)) if from <= to => vec![(from, to)],
+            _ => vec![],
+        }
+    }
+}
+
+/// The value of the digits an account starts with, `None` if it starts with a letter or the number
+/// doesn't fit in a `u32`.
+fn first_number(account: &AccountId) -> Option<u32> {
+    let text = account.as_str();
+    let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    match text[..digits].trim_start_matches('0') {
+        "" if digits > 0 => Some(0),
+        number => number.parse().ok(),
+    }
+}
+
+impl Range {
+    /// Returns true if the account is in one of the account ranges of the line and not excluded.
+    pub fn contains(&self, account: &AccountId) -> bool {
+        let (excluded, included): (Vec<_>, Vec<_>) = self.accounts.iter().partition(|a| a.excluded);
+        included.iter().any(|a| a.contains(account)) && !excluded.iter().any(|a| a.contains(account))
+    }
+
+    /// The accounts written as plain numbers the line captures as sorted, non overlapping
+    /// `(from, to)` pairs with both ends included. Exclusions are taken out and reversed ranges
+    /// don't capture anything. Accounts like `0150` or `A-4000` aren't plain numbers, use
+    /// `contains` for those.
+    pub fn intervals(&self) -> Vec<(u32, u32)> {
+        let mut included: Vec<(u32, u32)> = self
+            .accounts
+            .iter()
+            .filter(|a| !a.excluded)
+            .flat_map(|a| a.intervals())
+            .collect();
+        included.sort_unstable();
+
+        let mut merged: Vec<(u32, u32)> = vec![];
+        for (from, to) in included {
+            match merged.last_mut() {
+                Some(last) if from <= last.1.saturating_add(1) => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+
+        let excluded = self.accounts.iter().filter(|a| a.excluded).flat_map(|a| a.intervals());
+        for (ex_from, ex_to) in excluded {
+            let mut rest = vec![];
+            for (from, to) in merged {
+                if to < ex_from || ex_to < from {
+                    rest.push((from, to));
+                    continue;
+                }
+                if from < ex_from {
+                    rest.push((from, ex_from - 1));
+                }
+                if ex_to < to {
+                    rest.push((ex_to + 1, to));
+                }
+            }
+            merged = rest;
+        }
+
+        merged
+    }
+}
+
+/// Represents a Span which is the top level struct. A span looks like this
+/// ```ignore
+/// Optional title(
+///     3000..3050 => Webshop
+///     3050..4000 => Other sales
+/// ) Sum sales
+/// ```
+/// 
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub name: Option<String>,
+    /// The header in other languages, like `Sales` in `Salg | en: Sales (`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Translations::is_empty"))]
+    pub name_translations: Translations,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ranges: Vec<Range>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub subspans: Vec<Span>,
+    pub sum_type: SumType,
+    /// True if the header is written with a `-` in front like `-Sales (`, every amount in the span
+    /// is shown with the opposite sign.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub reverse_sign: bool,
+    /// Where in the source the header `Optional title(` was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Where in the source the `) => Sum sales` line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sum_location: Location,
+    /// Comment lines right before the header.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// The attributes like `#[page_break_before]` on the lines before the header.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Attributes::is_empty"))]
+    pub attributes: Attributes,
+    /// A comment after the `(` on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub header_comment: Option<String>,
+    /// Comment lines right before the `)`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub end_comments: Vec<String>,
+    /// A comment after the sum label on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub sum_comment: Option<String>,
+    /// The computed lines like `Gross profit = Sum sales - Sum material` written after the span.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub formulas: Vec<Formula>,
+    /// Comment lines after the last span in the input.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub trailing_comments: Vec<String>,
+    /// The `include "path.qa"` line in a block the span is from, with an empty `document`. Every
+    /// span of the file has the line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub include: Option<Include>,
+    /// The `use department(Sales, 30)` line the span is expanded from. Every location in an
+    /// expanded span is the location of the line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub template_use: Option<TemplateUse>,
+    /// The kind of the `report balance { .. }` the span is in, `None` for a span outside of a
+    /// report. A span from an included file keeps the kind of the report it's in in that file.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub report: Option<ReportKind>,
+    /// The name of the file the span is from if it was included from another file. The
+    /// locations of the span are in that file.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub file: Option<String>,
+}
+
+impl Span {
+    /// Sets every location in the span and its subspans that aren't from another file, for a span
+    /// expanded from a template.
+    fn set_location(&mut self, location: Location) {
+        self.location = location;
+        self.sum_location = location;
+        for range in &mut self.ranges {
+            range.location = location;
+        }
+        for subspan in self.subspans.iter_mut().filter(|subspan| subspan.file.is_none()) {
+            subspan.set_location(location);
+        }
+    }
+
+    /// Sets the file of the span and its subspans that aren't from another file.
+    fn set_file(&mut self, name: &str) {
+        if self.file.is_none() {
+            self.file = Some(name.to_string());
+        }
+        for subspan in &mut self.subspans {
+            subspan.set_file(name);
+        }
+    }
+}
+
+/// What a report sums, written as `report balance { .. }`, `report result { .. }` or `report
+/// opening { .. }` around the spans. It decides which balances `eval::evaluate_period` uses.
+///
+/// With the `serde` feature this is serialized as `"balance"`, `"result"` or `"opening"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ReportKind {
+    /// A balance sheet, it sums the balances at the end of the period.
+    Balance,
+    /// An income statement, it sums the movement in the period.
+    Result,
+    /// An opening balance sheet, it sums the balances at the start of the period.
+    Opening,
+}
+
+/// Represents a sum-type. SumTotal is the sum `(...) => Sum sales` of a top level `Span`. A
+/// `SubTotal` is the sum of a nested `Span`.
+///
+/// The `translations` are the label in other languages, like `Sum sales` in `) => Sum salg | en:
+/// Sum sales`.
+///
+/// With the `serde` feature this is serialized as `{ "kind": "sum_total", "label": "Sum sales" }`
+/// or `{ "kind": "sub_total", "label": null }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum SumType {
+    SumTotal {
+        label: Option<String>,
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Translations::is_empty"))]
+        translations: Translations,
+    },
+    SubTotal {
+        label: Option<String>,
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Translations::is_empty"))]
+        translations: Translations,
+    },
+}
+
+impl SumType {
+    /// The label of the sum, if any.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            SumType::SumTotal { label, .. } | SumType::SubTotal { label, .. } => label.as_deref(),
+        }
+    }
+
+    /// The label of the sum in other languages.
+    pub fn translations(&self) -> &Translations {
+        match self {
+            SumType::SumTotal { translations, .. } | SumType::SubTotal { translations, .. } => translations,
+        }
+    }
+
+    /// Makes it the sum of a nested span, for a block that is included or used in another block.
+    fn make_sub_total(&mut self) {
+        if let SumType::SumTotal { label, translations } = self {
+            *self = SumType::SubTotal { label: label.take(), translations: std::mem::take(translations) };
+        }
+    }
+}
+
+/// A computed line after a span like `Gross profit = Sum sales - Sum material`. It's the result of
+/// the expression, which can refer to the sum labels of the spans and the titles of other
+/// formulas.
+///
+/// All the members of this struct is public so you can access the data directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Formula {
+    pub title: String,
+    /// The title in other languages, like `Gross profit` in `Brutto | en: Gross profit = ..`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Translations::is_empty"))]
+    pub translations: Translations,
+    pub expr: Expr,
+    /// Where in the source the formula line was defined.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location: Location,
+    /// Comment lines right before the formula.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub comments: Vec<String>,
+    /// The attributes like `#[bold]` on the lines before the formula.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Attributes::is_empty"))]
+    pub attributes: Attributes,
+    /// A comment after the expression on the same line.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
+}
+
+/// The expression of a `Formula`. Parentheses aren't kept, the tree says what's computed first.
+///
+/// With the `serde` feature this is serialized as `{ "kind": "reference", "name": "Sum sales" }`,
+/// `{ "kind": "binary", "operator": "subtract", "left": .., "right": .. }` and so on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Expr {
+    /// A number like `100` or `0.25`.
+    Number { value: Amount },
+    /// The total of a span with this sum label, or the result of the formula with this title.
+    Reference { name: String },
+    /// `-expr`
+    Negate { operand: Box<Expr> },
+    Binary {
+        operator: Operator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+/// The operator of a binary `Expr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Expr {
+    /// The names the expression refers to, in the order they're written.
+    pub fn references(&self) -> Vec<&str> {
+        match self {
+            Expr::Number { .. } => vec![],
+            Expr::Reference { name } => vec![name.as_str()],
+            Expr::Negate { operand } => operand.references(),
+            Expr::Binary { left, right, .. } => {
+                let mut names = left.references();
+                names.extend(right.references());
+                names
+            }
+        }
+    }
+}
+
+/// Two ranges are equal if they describe the same line, where they're defined in the source is
+/// not compared.
+impl PartialEq for Range {
+    fn eq(&self, other: &Range) -> bool {
+        self.title == other.title
+            && self.translations == other.translations
+            && self.accounts == other.accounts
+            && self.sets == other.sets
+            && self.reverse_sign == other.reverse_sign
+            && self.comments == other.comments
+            && self.attributes == other.attributes
+            && self.comment == other.comment
+    }
+}
+
+impl Eq for Range {}
+
+/// Two spans are equal if they describe the same part of a report, where they're defined in the
+/// source is not compared.
+impl PartialEq for Span {
+    fn eq(&self, other: &Span) -> bool {
+        self.name == other.name
+            && self.name_translations == other.name_translations
+            && self.ranges == other.ranges
+            && self.subspans == other.subspans
+            && self.sum_type == other.sum_type
+            && self.reverse_sign == other.reverse_sign
+            && self.comments == other.comments
+            && self.attributes == other.attributes
+            && self.header_comment == other.header_comment
+            && self.end_comments == other.end_comments
+            && self.sum_comment == other.sum_comment
+            && self.formulas == other.formulas
+            && self.trailing_comments == other.trailing_comments
+            && self.include == other.include
+            && self.template_use == other.template_use
+            && self.report == other.report
+    }
+}
+
+impl Eq for Span {}
+
+/// Two formulas are equal if they compute the same line, where they're defined in the source is
+/// not compared.
+impl PartialEq for Formula {
+    fn eq(&self, other: &Formula) -> bool {
+        self.title == other.title
+            && self.translations == other.translations
+            && self.expr == other.expr
+            && self.comments == other.comments
+            && self.attributes == other.attributes
+            && self.comment == other.comment
+    }
 }
 
-/// Represents a Span which is the top level struct. A span looks like this
-/// ```ignore
-/// Optional title(
-///     3000..3050 => Webshop
-///     3050..4000 => Other sales
-/// ) Sum sales
-/// ```
-/// 
-/// All the members of this struct is public so you can access the data directly.
-#[derive(Debug)]
-pub struct Span {
-    pub name: Option<String>,
-    pub ranges: Vec<Range>,
-    pub subspans: Vec<Span>,
-    pub sum_type: SumType,
+impl Eq for Formula {}
+
+/// Two range sets are equal if they have the same name and accounts, where they're defined in the
+/// source is not compared.
+impl PartialEq for RangeSet {
+    fn eq(&self, other: &RangeSet) -> bool {
+        self.name == other.name
+            && self.accounts == other.accounts
+            && self.comments == other.comments
+            && self.comment == other.comment
+    }
 }
 
-/// Represents a sum-type. SumTotal is the sum `(...) => Sum sales` of a top level `Span`. A
-/// `SubTotal` is the sum of a nested `Span`.
-#[derive(Debug)]
-pub enum SumType {
-    SumTotal(Option<String>),
-    SubTotal(Option<String>),
+impl Eq for RangeSet {}
+
+/// Two templates are equal if they have the same name, parameters and block, where they're defined
+/// in the source is not compared.
+impl PartialEq for Template {
+    fn eq(&self, other: &Template) -> bool {
+        self.name == other.name
+            && self.parameters == other.parameters
+            && self.body == other.body
+            && self.comments == other.comments
+            && self.comment == other.comment
+    }
 }
 
+impl Eq for Template {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -666,6 +3561,18 @@ mod tests {
     ) => Sum other costs
     ";
 
+    /// The kind, line and column of the errors `parse_recovering` finds in the test.
+    fn errors(test: &str) -> Vec<(ErrorKind, usize, usize)> {
+        let (_, errors) = Parser::new(test).parse_recovering();
+        errors.iter().map(|e| (e.kind, e.line(), e.column())).collect()
+    }
+
+    /// Like `errors` with the file each error is in, for a test read as `file` with the loader.
+    fn file_errors(test: &str, file: &str, loader: impl Loader + 'static) -> Vec<(ErrorKind, Option<String>, usize, usize)> {
+        let (_, errors) = Parser::new(test).file(file).loader(loader).parse_recovering();
+        errors.iter().map(|e| (e.kind, e.file.clone(), e.line(), e.column())).collect()
+    }
+
     #[test]
     fn parse_full_syntax() {
         let mut parser = Parser::new(TEST);
@@ -739,10 +3646,39 @@ ERROR: Expected >
         let mut parser = Parser::new(test);
         match parser.parse() {
             Ok(_) => (),
-            Err(e) => assert_eq!(e, expected_err),
+            Err(e) => assert_eq!(e.to_string(), expected_err),
          }
     }
 
+    #[test]
+    fn reports_input_after_the_last_block() {
+        let test = "(\n    3000 => Sales\n) => Sum sales\nSales 3100\n";
+
+        // parse stops there, parse_recovering reports it
+        let ast = Parser::new(test).parse().unwrap();
+        assert_eq!(ast.len(), 1);
+
+        let (ast, errors) = Parser::new(test).parse_recovering();
+        assert_eq!(ast.len(), 1);
+        assert_eq!((errors[0].kind, errors[0].line(), errors[0].column()), (ErrorKind::ExpectedBlock, 4, 1));
+    }
+
+    #[test]
+    fn titles_end_at_the_line_end() {
+        // an empty title doesn't take the next line
+        let test = "(\n    3000 =>\n    3100 => B\n    (\n        4000 => C\n    ) =>\n) =>\n(\n    5000 => D\n) => Sum\n";
+        let ast = Parser::new(test).parse().unwrap();
+        assert_eq!(ast.len(), 2);
+        let titles: Vec<_> = ast[0].ranges.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, vec!["", "B"]);
+        assert_eq!(ast[0].subspans[0].sum_type.name(), Some(""));
+        assert_eq!(ast[0].sum_type.name(), Some(""));
+
+        // the `=>` has to be on the line of the accounts
+        let err = Parser::new("(\n    3000\n    => A\n) => Sum").parse().unwrap_err();
+        assert_eq!((err.kind, err.line()), (ErrorKind::ExpectedArrow, 2));
+    }
+
     #[test]
     fn reports_range_err() {
         let test = "
@@ -766,7 +3702,934 @@ ERROR: Invalid range syntax
         let mut parser = Parser::new(test);
         match parser.parse() {
             Ok(_) => (),
-            Err(e) => assert_eq!(e, expected_err),
+            Err(e) => assert_eq!(e.to_string(), expected_err),
          }
     }
+
+    #[test]
+    fn reports_missing_arrow() {
+        let err = Parser::new("(\n    3000..3999\n) => S").parse().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::ExpectedArrow));
+        assert_eq!((err.line(), err.column()), (2, 15));
+        assert!(err.to_string().ends_with("ERROR: Expected =>\n"));
+
+        let err = Parser::new("(\n    3000 Sales\n) => S").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::ExpectedArrow, 2, 10));
+    }
+
+    #[test]
+    fn parses_single_accounts() {
+        let test = "(\n    3010 => Webshop\n    3020=>Other sales\n    3030..3030 => Kiosk\n) => Sum sales";
+
+        let mut parser = Parser::new(test);
+        let ast = parser.parse().unwrap();
+        let ranges = &ast[0].ranges;
+
+        let accounts: Vec<_> = ranges
+            .iter()
+            .map(|r| (r.accounts[0].from.as_str(), r.accounts[0].to.as_str(), r.accounts[0].single_account))
+            .collect();
+        assert_eq!(accounts, vec![("3010", "3010", true), ("3020", "3020", true), ("3030", "3030", false)]);
+        assert_eq!(ranges[0].title, "Webshop");
+        assert_eq!(ranges[1].title, "Other sales");
+
+        let err = Parser::new("(\n    3010y => Webshop\n) => Sum").parse().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidRangeSyntax);
+    }
+
+    #[test]
+    fn parses_account_lists() {
+        let test = "(\n    3000..3999, 8100 ,!3010, !3500..3599 => Other sales\n) => Sum sales";
+
+        let mut parser = Parser::new(test);
+        let ast = parser.parse().unwrap();
+        let range = &ast[0].ranges[0];
+
+        let accounts: Vec<_> = range.accounts.iter().map(|a| (a.from.as_str(), a.to.as_str(), a.excluded)).collect();
+        assert_eq!(accounts, vec![("3000", "3999", false), ("8100", "8100", false), ("3010", "3010", true), ("3500", "3599", true)]);
+        assert_eq!(range.title, "Other sales");
+        assert_eq!(range.intervals(), vec![(3000, 3009), (3011, 3499), (3600, 3999), (8100, 8100)]);
+        assert!(range.contains(&3011.into()) && range.contains(&8100.into()));
+        assert!(!range.contains(&3010.into()) && !range.contains(&3550.into()));
+
+        let err = Parser::new("(\n    !3010, !3020 => Sales\n) => Sum").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::OnlyExclusions, 2, 5));
+        let err = Parser::new("(\n    3000, => Sales\n) => Sum").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidRange, 2, 11));
+    }
+
+    #[test]
+    fn parses_account_identifiers() {
+        let test = "
+        Bank (
+            1920.01..1920.99 => Bank accounts
+        ) => Sum bank
+        Sales (
+            A-4000, 0150 => Other
+            Misc (
+                0150..0200 => Misc
+            ) => Sum misc
+        ) => Sum sales";
+
+        // letters and dots are only allowed if we ask for them
+        let err = Parser::new(test).parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidRangeSyntax, 3, 18));
+
+        let mut parser = Parser::new(test).account_syntax(AccountSyntax::Alphanumeric);
+        let ast = parser.parse().unwrap();
+
+        let range = &ast[0].ranges[0];
+        assert_eq!((range.accounts[0].from.as_str(), range.accounts[0].to.as_str()), ("1920.01", "1920.99"));
+        assert!(range.contains(&"1920.5".parse().unwrap()) && !range.contains(&"1921".parse().unwrap()));
+        assert!(range.intervals().is_empty());
+
+        let range = &ast[1].ranges[0];
+        let accounts: Vec<_> = range.accounts.iter().map(|a| a.from.as_str()).collect();
+        assert_eq!(accounts, vec!["A-4000", "0150"]);
+        assert!(!range.contains(&150.into()));
+        assert_eq!(ast[1].subspans[0].name.as_deref(), Some("Misc"));
+        // 0150 isn't a plain number, but 150 comes after it
+        assert_eq!(ast[1].subspans[0].ranges[0].intervals(), vec![(150, 199)]);
+
+        let err = Parser::new("(\n    A-..B => Sales\n) => Sum").account_syntax(AccountSyntax::Alphanumeric).parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidAccount, 2, 5));
+    }
+
+    #[test]
+    fn parses_account_patterns() {
+        let test = "(\n    30xx, 8*, !301X, 03xx, A-4* => Sales\n) => Sum sales";
+
+        let mut parser = Parser::new(test).account_syntax(AccountSyntax::Alphanumeric);
+        let ast = parser.parse().unwrap();
+        let range = &ast[0].ranges[0];
+
+        let digits = |prefix: &str, wildcards| Some(AccountPattern::Digits { prefix: prefix.to_string(), wildcards });
+        let accounts: Vec<_> = range.accounts.iter().map(|a| (a.from.as_str(), a.to.as_str(), a.pattern.clone())).collect();
+        assert_eq!(accounts, vec![
+            ("3000", "3099", digits("30", 2)),
+            ("8", "8", Some(AccountPattern::Prefix { prefix: "8".to_string() })),
+            ("3010", "3019", digits("301", 1)),
+            ("0300", "0399", digits("03", 2)),
+            ("A-4", "A-4", Some(AccountPattern::Prefix { prefix: "A-4".to_string() })),
+        ]);
+
+        // `30xx` only matches four digit accounts, `8*` any length
+        let contains = |account: &str| range.contains(&account.parse().unwrap());
+        assert!(contains("3000") && contains("3099") && contains("3020") && contains("0301"));
+        assert!(!contains("300") && !contains("30000") && !contains("3015") && !contains("301"));
+        assert!(contains("8") && contains("81") && contains("8100.01") && contains("A-4000"));
+        assert!(!contains("9") && !contains("7999") && !contains("3000.01"));
+        assert_eq!(range.intervals()[..5], [(8, 8), (80, 89), (800, 899), (3000, 3009), (3020, 3099)]);
+        assert_eq!(AccountPattern::Prefix { prefix: "4".to_string() }.intervals().last(), Some(&(4_000_000_000, u32::MAX)));
+
+        // the patterns are the same with only digits
+        let digits = Parser::new("(\n    30xx, 8*, !301X, 03xx => Sales\n) => Sum sales").parse().unwrap();
+        assert_eq!(digits[0].ranges[0].accounts[..], range.accounts[..4]);
+
+        let err = Parser::new("(\n    3x10 => Sales\n) => Sum").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidPattern, 2, 7));
+        let err = Parser::new("(\n    3*0 => Sales\n) => Sum").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidPattern, 2, 7));
+        let err = Parser::new("(\n    30xx* => Sales\n) => Sum").account_syntax(AccountSyntax::Alphanumeric).parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::InvalidPattern, 2, 9));
+    }
+
+    #[test]
+    fn parses_reversed_signs() {
+        let test = "
+        -Sales (
+            -3000..3999, !3010 => Sales
+            -A-4000 => Other
+            -Costs (
+                4000 => Material
+            ) => Sum costs
+        ) => Sum sales
+        -(
+            -!3010 => Nothing
+        ) => Sum
+        ";
+
+        let (ast, errors) = Parser::new(test).account_syntax(AccountSyntax::Alphanumeric).parse_recovering();
+        assert!(ast[0].reverse_sign);
+        assert_eq!(ast[0].name.as_deref(), Some("Sales"));
+        assert!(ast[0].ranges[0].reverse_sign);
+        assert_eq!(ast[0].ranges[0].accounts[1].from.as_str(), "3010");
+        assert_eq!(ast[0].ranges[1].accounts[0].from.as_str(), "A-4000");
+        assert!(ast[0].subspans[0].reverse_sign);
+        assert!(!ast[0].subspans[0].ranges[0].reverse_sign);
+        assert!(ast[1].reverse_sign && ast[1].name.is_none());
+
+        let errors: Vec<_> = errors.iter().map(|e| (e.kind, e.line(), e.column())).collect();
+        assert_eq!(errors, vec![(ErrorKind::OnlyExclusions, 10, 13)]);
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let test = "
+        # not an attribute
+        #[page_break_before]
+        Sales (
+            #[hide_if_zero, note = 7]
+            // comment
+            #[label = \"Webshop \\\"new\\\"\"] // after
+            3010 => Webshop # [comment]
+            #[bold]
+            (
+                3020 => Other
+            ) => Sum other
+        ) => Sum sales
+        #[drilldown = false]
+        Result = Sum sales
+        ";
+
+        let ast = Parser::new(test).parse().unwrap();
+        let attributes = |pairs: &[(&str, &str)]| -> Attributes {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(ast[0].attributes, attributes(&[("page_break_before", "true")]));
+        assert_eq!(ast[0].comments, vec!["not an attribute"]);
+        assert_eq!(ast[0].ranges[0].attributes, attributes(&[("hide_if_zero", "true"), ("note", "7"), ("label", "Webshop \"new\"")]));
+        assert_eq!(ast[0].ranges[0].comments, vec!["comment", "after"]);
+        assert_eq!(ast[0].ranges[0].comment.as_deref(), Some("[comment]"));
+        assert_eq!(ast[0].subspans[0].attributes, attributes(&[("bold", "true")]));
+        assert_eq!(ast[0].formulas[0].attributes, attributes(&[("drilldown", "false")]));
+
+        let test = "(\n#[a, b =]\n#[a, a]\n#[a = \"x\\y\"]\n#[a = \"x]\n#[a] b\n#[a\n3000 => A\n#[a]\n) => Sum\n#[a]";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::InvalidAttribute, 2, 9),
+            (ErrorKind::DuplicateAttribute, 3, 6),
+            (ErrorKind::InvalidEscape, 4, 9),
+            (ErrorKind::UnclosedString, 5, 7),
+            (ErrorKind::UnexpectedSyntax, 6, 6),
+            (ErrorKind::InvalidAttribute, 7, 4),
+            (ErrorKind::DanglingAttributes, 9, 1),
+            (ErrorKind::DanglingAttributes, 11, 1),
+        ]);
+    }
+
+    #[test]
+    fn parses_quoted_titles() {
+        let test = r#"
+        "Depreciation (machinery)" ( // header
+            6000..6099 => "Leasing # 1" // comment
+            6100 => "\"Quoted\"\tand \\ escaped"
+            6200 =>   Not quoted "here" // comment
+            -"Costs = (x)" (
+                6300 => ""
+            ) => "Sum ) => ("
+        ) => "  Sum  " # sum
+        "Result (after tax)" = "Sum  " - [Sum ) => (] * "Leasing # 1"
+        "#;
+
+        let ast = Parser::new(test).parse().unwrap();
+        let span = &ast[0];
+        assert_eq!(span.name.as_deref(), Some("Depreciation (machinery)"));
+        assert_eq!(span.header_comment.as_deref(), Some("header"));
+        assert_eq!(span.ranges[0].title, "Leasing # 1");
+        assert_eq!(span.ranges[0].comment.as_deref(), Some("comment"));
+        assert_eq!(span.ranges[1].title, "\"Quoted\"\tand \\ escaped");
+        assert_eq!(span.ranges[2].title, "Not quoted \"here\"");
+        assert_eq!(span.subspans[0].name.as_deref(), Some("Costs = (x)"));
+        assert!(span.subspans[0].reverse_sign);
+        assert_eq!(span.subspans[0].ranges[0].title, "");
+        assert_eq!(span.subspans[0].sum_type.name(), Some("Sum ) => ("));
+        assert_eq!(span.sum_type.name(), Some("  Sum  "));
+        assert_eq!(span.sum_comment.as_deref(), Some("sum"));
+        assert_eq!((span.ranges[0].location.start.column, span.ranges[0].location.end.column), (13, 40));
+
+        let formula = &span.formulas[0];
+        assert_eq!(formula.title, "Result (after tax)");
+        assert_eq!(formula.expr.references(), vec!["Sum  ", "Sum ) => (", "Leasing # 1"]);
+
+        let test = "\"A\" x (\n3000 => \"A\" b\n3000 => \"A\n) => \"\\q\"\n\"B\" + 1 = 2\nC = \"\"";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::UnexpectedSyntax, 1, 5),
+            (ErrorKind::UnexpectedSyntax, 2, 13),
+            (ErrorKind::UnclosedString, 3, 9),
+            (ErrorKind::InvalidEscape, 4, 7),
+            (ErrorKind::UnexpectedSyntax, 5, 5),
+            (ErrorKind::ExpectedOperand, 6, 5),
+        ]);
+    }
+
+    #[test]
+    fn parses_translations() {
+        let test = r#"
+        Salg | en: Sales | de-AT: "Umsatz (netto)" (
+            3000 => Webshop | en: Web shop // comment
+            3100 => Service | note: 7
+            3150 => Taxes | VAT: 25%
+            3200 => | en: Other
+        ) => Sum salg | en: Sum sales
+        Brutto | en: "Gross = profit" = Sum salg
+        "#;
+
+        let ast = Parser::new(test).parse().unwrap();
+        let span = &ast[0];
+        let translations = |pairs: &[(&str, &str)]| -> Translations {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(span.name.as_deref(), Some("Salg"));
+        assert_eq!(span.name_translations, translations(&[("en", "Sales"), ("de-AT", "Umsatz (netto)")]));
+        assert_eq!(span.ranges[0].title, "Webshop");
+        assert_eq!(span.ranges[0].translations, translations(&[("en", "Web shop")]));
+        assert_eq!(span.ranges[0].comment.as_deref(), Some("comment"));
+        assert_eq!(span.ranges[0].location.end.column, 43);
+        assert_eq!(span.ranges[1].title, "Service | note: 7");
+        assert!(span.ranges[1].translations.is_empty());
+        assert_eq!(span.ranges[2].title, "Taxes | VAT: 25%");
+        assert!(span.ranges[2].translations.is_empty());
+        assert_eq!(span.ranges[3].title, "");
+        assert_eq!(span.ranges[3].translations, translations(&[("en", "Other")]));
+        assert_eq!(span.sum_type.name(), Some("Sum salg"));
+        assert_eq!(span.sum_type.translations(), &translations(&[("en", "Sum sales")]));
+        assert_eq!(span.formulas[0].title, "Brutto");
+        assert_eq!(span.formulas[0].translations, translations(&[("en", "Gross = profit")]));
+
+        assert_eq!(span.localized_name(&["de-AT"]), Some("Umsatz (netto)"));
+        assert_eq!(span.sum_type.localized_name(&["en-US"]), Some("Sum sales"));
+        assert_eq!(span.ranges[1].localized_title(&["en"]), "Service | note: 7");
+
+        let test = "A | en: B | en: C (\n3000 => A | en: \"B\" x\n) => S | de: x | de: y";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::DuplicateTranslation, 1, 11),
+            (ErrorKind::UnexpectedSyntax, 2, 21),
+            (ErrorKind::DuplicateTranslation, 3, 16),
+        ]);
+    }
+
+    #[test]
+    fn parses_formulas() {
+        let test = "
+        (
+            3000..3999 => Sales
+        ) => Sum sales
+
+        // gross
+        Gross profit = Sum sales - Sum material - [Sum non-operating costs] # comment
+        Margin = -(Gross profit) / Sum sales * 100
+        ";
+
+        let ast = Parser::new(test).parse().unwrap();
+        let formulas = &ast[0].formulas;
+        assert_eq!(formulas.len(), 2);
+        assert_eq!(formulas[0].title, "Gross profit");
+        assert_eq!(formulas[0].comments, vec!["gross"]);
+        assert_eq!(formulas[0].comment.as_deref(), Some("comment"));
+        assert_eq!(formulas[0].expr.references(), vec!["Sum sales", "Sum material", "Sum non-operating costs"]);
+        assert_eq!((formulas[0].location.start.line, formulas[0].location.end.column), (7, 76));
+
+        let reference = |name: &str| Box::new(Expr::Reference { name: name.to_string() });
+        let expected = Expr::Binary {
+            operator: Operator::Multiply,
+            left: Box::new(Expr::Binary {
+                operator: Operator::Divide,
+                left: Box::new(Expr::Negate { operand: reference("Gross profit") }),
+                right: reference("Sum sales"),
+            }),
+            right: Box::new(Expr::Number { value: Amount::from_int(100) }),
+        };
+        assert_eq!(formulas[1].expr, expected);
+
+        let sales = "(\n    3000 => Sales\n) => Sum sales\n";
+        assert_eq!(errors(&format!("{}= Sum sales\nA = Sum sales -\nB = (Sum sales\nC = [Sum sales\nD = A ) B\nE = 1 2 [A]", sales)), vec![
+            (ErrorKind::MissingFormulaTitle, 4, 1),
+            (ErrorKind::ExpectedOperand, 5, 16),
+            (ErrorKind::ExpectedCloseParen, 6, 15),
+            (ErrorKind::UnclosedReference, 7, 5),
+            (ErrorKind::UnexpectedSyntax, 8, 7),
+            (ErrorKind::UnexpectedSyntax, 9, 9),
+        ]);
+        assert_eq!(errors(&format!("{}A = {}1", sales, "(".repeat(200))), vec![(ErrorKind::FormulaTooLong, 4, 133)]);
+
+        // a formula is found without a span before it, and a header can have a `=` in it
+        let err = Parser::new("\n// first\nA = B\n(\n    3000 => Sales\n) => B\n").parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::FormulaBeforeBlock, 3, 1));
+        let ast = Parser::new("Revenue = net ( // sales\n    3000 => Sales\n) => B\nA = B // (\nAccount #5 = A\n").parse().unwrap();
+        assert_eq!(ast[0].name.as_deref(), Some("Revenue = net"));
+        assert_eq!(ast[0].header_comment.as_deref(), Some("sales"));
+        assert_eq!(ast[0].formulas[0].title, "A");
+        assert_eq!(ast[0].formulas[1].title, "Account #5");
+        assert_eq!(format(&ast), "\"Revenue = net\" ( // sales\n    3000 => Sales\n) => B\n\nA = B // (\n\"Account #5\" = A\n");
+    }
+
+    #[test]
+    fn parses_includes() {
+        let loader = || {
+            MemoryLoader::new()
+                .file("sales.qa", "Sales (\n    3000..3999 => Sales\n) => Sum sales\n")
+                .file("costs.qa", "// costs\n(\n    4000 => Material\n) => Sum material\n\n(\n    5000 => Labor\n) => Sum labor\n")
+                .file("bad.qa", "(\n    4000 => Material\n    40.00 => Other\n) => Sum material\n")
+                .file("loop.qa", "include \"cycle.qa\"\n")
+                .file("cycle.qa", "include \"loop.qa\"\n")
+                .file("empty.qa", "// nothing\n")
+        };
+
+        let test = "
+        // the sales
+        include \"sales.qa\"
+        Other ( // other
+            include \"costs.qa\" # both of them
+        ) => Sum other
+        Gross profit = Sum sales - Sum other
+        ";
+
+        let ast = Parser::new(test).loader(loader()).parse().unwrap();
+        assert_eq!(ast.len(), 2);
+        assert_eq!(ast[0].name.as_deref(), Some("Sales"));
+        assert!(ast[0].comments.is_empty());
+        assert_eq!(ast[0].sum_type, SumType::SumTotal { label: Some("Sum sales".to_string()), translations: Translations::new() });
+        assert_eq!(ast[0].file.as_deref(), Some("sales.qa"));
+        assert_eq!(ast[0].location.start.line, 1);
+        assert_eq!(ast[1].subspans.len(), 2);
+        assert_eq!(ast[1].subspans[0].comments, vec!["costs"]);
+        assert_eq!(ast[1].subspans[1].sum_type, SumType::SubTotal { label: Some("Sum labor".to_string()), translations: Translations::new() });
+        assert_eq!(ast[1].subspans[1].file.as_deref(), Some("costs.qa"));
+        assert_eq!(ast[1].file, None);
+        assert_eq!(ast[1].formulas[0].title, "Gross profit");
+
+        // the spans of a file included in a block have the line
+        let costs = ast[1].subspans[0].include.as_ref().unwrap();
+        assert_eq!((costs.path.as_str(), costs.comment.as_deref()), ("costs.qa", Some("both of them")));
+        assert_eq!((costs.location.start.column, costs.location.end.column), (13, 31));
+        assert!(costs.document.items.is_empty());
+        assert_eq!(ast[1].subspans[1].include, ast[1].subspans[0].include);
+
+        // the include lines are items of the document, with the items of the file
+        let document = Parser::new(test).loader(loader()).parse_document().unwrap();
+        assert_eq!(document.spans(), ast);
+        let include = match &document.items[0] {
+            Item::Include(include) => include,
+            _ => panic!("expected an include"),
+        };
+        assert_eq!((include.path.as_str(), include.file.as_str()), ("sales.qa", "sales.qa"));
+        assert_eq!(include.comments, vec!["the sales"]);
+        assert_eq!(include.location.start.line, 3);
+        assert_eq!(include.document.items.len(), 1);
+
+        // without a loader the files can't be read
+        let err = Parser::new(test).parse_document().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::NoLoader, 3, 9));
+
+        // a file the loader can't read has the reason in the note
+        let err = Parser::new("include \"missing.qa\"\n").loader(loader()).parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::IncludeFailed, 1, 1));
+        assert_eq!(err.note.as_deref(), Some("missing.qa: missing.qa not found"));
+        assert!(err.to_string().ends_with("ERROR: Could not read the included file\nNOTE: missing.qa: missing.qa not found\n"));
+        let (_, errors) = Parser::new("include \"missing.qa\"\n(\n    3000 => Sales\n) => Sum\n=\n").loader(loader()).parse_recovering();
+        assert_eq!(errors[0].note.as_deref(), Some("missing.qa: missing.qa not found"));
+        assert_eq!((errors[1].kind, errors[1].note.as_deref()), (ErrorKind::MissingFormulaTitle, None));
+
+        // the errors in an included file are in that file
+        let err = Parser::new("include \"bad.qa\"\n").file("main.qa").loader(loader()).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line(), err.column()), (ErrorKind::InvalidRangeSyntax, Some("bad.qa"), 3, 8));
+        assert!(err.to_string().starts_with("\nfile: bad.qa, line: 3, pos: 8\n    40.00 => Other\n"));
+
+        let main = || Some("main.qa".to_string());
+        let test = "include \"bad.qa\"\ninclude \"missing.qa\"\ninclude \"loop.qa\"\n#[bold]\ninclude \"sales.qa\"\ninclude \"main.qa\" x\n";
+        assert_eq!(file_errors(test, "main.qa", loader()), vec![
+            (ErrorKind::InvalidRangeSyntax, Some("bad.qa".to_string()), 3, 8),
+            (ErrorKind::IncludeFailed, main(), 2, 1),
+            (ErrorKind::CircularInclude, Some("cycle.qa".to_string()), 1, 1),
+            (ErrorKind::DanglingAttributes, main(), 5, 1),
+            (ErrorKind::UnexpectedSyntax, main(), 6, 19),
+        ]);
+        assert_eq!(file_errors("include \"main.qa\"\n", "main.qa", loader()), vec![(ErrorKind::CircularInclude, main(), 1, 1)]);
+        assert_eq!(file_errors("(\n    include \"empty.qa\"\n) => Sum\n", "main.qa", loader()), vec![(ErrorKind::EmptyInclude, main(), 2, 5)]);
+
+        // the name of the input is resolved before it's compared with the included files
+        struct Files(MemoryLoader);
+        impl Loader for Files {
+            fn resolve(&self, path: &str, from: Option<&str>) -> String {
+                FileLoader::new().resolve(path, from)
+            }
+            fn load(&self, name: &str) -> std::io::Result<String> {
+                self.0.load(name)
+            }
+        }
+        let files = Files(loader().file("reports/sales.qa", "include \"../reports/main.qa\"\n"));
+        let err = Parser::new("include \"main.qa\"\n").file("reports/./main.qa").loader(files).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line()), (ErrorKind::CircularInclude, Some("reports/./main.qa"), 1));
+        let files = Files(loader().file("reports/sales.qa", "include \"../reports/main.qa\"\n"));
+        let err = Parser::new("include \"sales.qa\"\n").file("./reports/main.qa").loader(files).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line()), (ErrorKind::CircularInclude, Some("reports/sales.qa"), 1));
+
+        // a parser with a loader can be sent to another thread
+        fn is_send<T: Send>(_: &T) {}
+        is_send(&Parser::new("").loader(loader()));
+        assert_eq!(file_errors("include \"sales.qa\n", "main.qa", loader()), vec![(ErrorKind::UnclosedString, main(), 1, 9)]);
+    }
+
+    #[test]
+    fn parses_range_sets() {
+        let test = "
+        // the bank
+        let bank = 1900..1999, !1950 # not that one
+        let cash = 19xx
+        Assets (
+            -@bank, 1800 => Bank
+            @cash, @bank => Cash
+            @loans => Loans
+        ) => Sum assets
+        let late = 2000
+        Total = Sum assets
+        ";
+
+        let document = Parser::new(test).parse_document().unwrap();
+        assert_eq!(document.items.len(), 5);
+        let mut sets: Vec<RangeSet> = document.range_sets().into_iter().cloned().collect();
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0].name, "bank");
+        assert_eq!(sets[0].comments, vec!["the bank"]);
+        assert_eq!(sets[0].comment.as_deref(), Some("not that one"));
+        assert_eq!(sets[0].accounts.len(), 2);
+        assert_eq!((sets[1].location.start.line, sets[1].location.end.column), (4, 24));
+        assert_eq!(sets[2].name, "late");
+        assert!(matches!(&document.items[4], Item::Formula(formula) if formula.title == "Total"));
+
+        // the spans are just the blocks, with the formula after the `let` line
+        let mut ast = Parser::new(test).parse().unwrap();
+        assert_eq!(ast, document.spans());
+        assert_eq!(ast.len(), 1);
+        assert_eq!(ast[0].formulas[0].title, "Total");
+        let ranges = &ast[0].ranges;
+        let accounts = |range: &Range| range.accounts.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert!(ranges[0].reverse_sign);
+        assert_eq!(accounts(&ranges[0]), vec!["1900..1999", "!1950", "1800"]);
+        assert_eq!(ranges[0].sets, vec![SetReference { name: "bank".to_string(), start: 0, len: 2 }]);
+        assert_eq!(accounts(&ranges[1]), vec!["19xx", "1900..1999", "!1950"]);
+        assert!(ranges[1].contains(&AccountId::from(1920)) && !ranges[1].contains(&AccountId::from(1950)));
+        assert!(accounts(&ranges[2]).is_empty());
+        assert_eq!(ranges[2].sets, vec![SetReference { name: "loans".to_string(), start: 0, len: 0 }]);
+
+        // resolving again gives the same accounts, with the sets as they are now
+        let before = ast.clone();
+        resolve_range_sets(&mut ast, &sets);
+        assert_eq!(ast, before);
+        sets[0].accounts.truncate(1);
+        resolve_range_sets(&mut ast, &sets);
+        assert_eq!(accounts(&ast[0].ranges[1]), vec!["19xx", "1900..1999"]);
+        assert_eq!(accounts(&ast[0].ranges[0]), vec!["1900..1999", "1800"]);
+
+        // the sets are resolved after the included files are read
+        let loader = MemoryLoader::new().file("accounts.qa", "let bank = 1900..1999\n");
+        let test = "(\n    @bank => Bank\n) => Sum\ninclude \"accounts.qa\"\n";
+        let ast = Parser::new(test).loader(loader).parse().unwrap();
+        assert_eq!(ast[0].ranges[0].accounts.len(), 1);
+
+        // a file included in a block can only have blocks
+        let loader = MemoryLoader::new().file("accounts.qa", "let bank = 1900..1999\n");
+        let err = Parser::new("(\n    include \"accounts.qa\"\n) => Sum\n").loader(loader).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line()), (ErrorKind::ExpectedBlock, Some("accounts.qa"), 1));
+
+        let test = "let = 1900\nlet bank = @cash\nlet bank = \n#[bold]\nlet cash = 1920\n(\n    @ => Bank\n    !1950 => Bank\n    @bank, !1950 => Bank\n) => Sum";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::FormulaBeforeBlock, 1, 1),
+            (ErrorKind::InvalidRange, 2, 12),
+            (ErrorKind::InvalidRange, 3, 12),
+            (ErrorKind::DanglingAttributes, 5, 1),
+            (ErrorKind::ExpectedName, 7, 6),
+            (ErrorKind::OnlyExclusions, 8, 5),
+        ]);
+    }
+
+    #[test]
+    fn parses_templates() {
+        let test = "
+        // one per department
+        template department(title, base) # t
+        {title} (
+            {base}000..{base}499, !{base}1xx => Sales
+            (
+                {base}500..{base}999 => Costs
+            ) => Sum costs {title}
+        ) => Sum {title}
+        // Oslo
+        use department(\"Oslo office\", 10) # o
+        (
+            use department(Bergen, 20)
+        ) => Sum
+        ";
+
+        let document = Parser::new(test).parse_document().unwrap();
+        assert_eq!(document.items.len(), 3);
+        assert!(matches!(&document.items[0], Item::Template(_)));
+        let template = document.templates()[0];
+        assert_eq!(template.name, "department");
+        assert_eq!(template.parameters, vec!["title", "base"]);
+        assert_eq!(template.comments, vec!["one per department"]);
+        assert_eq!(template.comment.as_deref(), Some("t"));
+        assert!(template.body.starts_with("        {title} (\n") && template.body.ends_with(") => Sum {title}"));
+
+        // the same span as if the block was written out, apart from the use line
+        let expected = "
+        Oslo office (
+            10000..10499, !101xx => Sales
+            (
+                10500..10999 => Costs
+            ) => Sum costs Oslo office
+        ) => Sum Oslo office
+        ";
+        let ast = Parser::new(test).parse().unwrap();
+        assert_eq!(ast, document.spans());
+        let mut oslo = ast[0].clone();
+        assert_eq!(oslo.comments, vec!["Oslo"]);
+        let template_use = oslo.template_use.take().unwrap();
+        assert_eq!(template_use.arguments, vec!["Oslo office", "10"]);
+        assert_eq!(template_use.comment.as_deref(), Some("o"));
+        oslo.comments.clear();
+        assert_eq!(vec![oslo], Parser::new(expected).parse().unwrap());
+
+        // the locations are the use line
+        let location = ast[0].location;
+        assert_eq!((location.start.line, location.start.column, location.end.column), (11, 9, 42));
+        assert_eq!(ast[0].subspans[0].ranges[0].location, location);
+
+        let bergen = &ast[1].subspans[0];
+        assert_eq!(bergen.name.as_deref(), Some("Bergen"));
+        assert!(matches!(&bergen.sum_type, SumType::SubTotal { label: Some(label), .. } if label == "Sum Bergen"));
+
+        // a template defined in an included file can be used after the include
+        let loader = MemoryLoader::new().file("templates.qa", "template cash(n)\n(\n    19{n}0 => Cash\n) => Sum\n");
+        let test = "include \"templates.qa\"\nuse cash(2)\n";
+        let document = Parser::new(test).loader(loader.clone()).parse_document().unwrap();
+        assert_eq!(document.templates()[0].name, "cash");
+        let ast = document.into_spans();
+        assert_eq!(ast.len(), 1);
+        assert_eq!(ast[0].ranges[0].accounts[0].to_string(), "1920");
+        // but not in a block
+        let err = Parser::new("(\n    include \"templates.qa\"\n) => Sum\n").loader(loader).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line()), (ErrorKind::ExpectedBlock, Some("templates.qa"), 1));
+
+        let test = "template a(x)\n(\n    {y}00 => A\n) => Sum\ntemplate b(x, x)\ntemplate c(x)\n(\n    {x}0 => C\n) => Sum\nuse c()\nuse c(A)\n#[bold]\nuse c(1)\ntemplate d()\n(\n    use d()\n) => Sum\nuse d()\nuse c(1\nuse e(1)\ntemplate e(x)\n(\n    use c(\"1\\n2\")\n) => Sum";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::UnknownParameter, 3, 5),
+            (ErrorKind::DuplicateParameter, 5, 1),
+            (ErrorKind::ArgumentCount, 10, 1),
+            (ErrorKind::ExpectedCloseParen, 11, 1),
+            (ErrorKind::DanglingAttributes, 13, 1),
+            (ErrorKind::CircularTemplate, 18, 1),
+            (ErrorKind::UnexpectedSyntax, 19, 8),
+            (ErrorKind::UndefinedTemplate, 20, 1),
+        ]);
+
+        // an error in the block of a template is shown at the use line with the argument
+        let err = Parser::new(test).parse().unwrap_err();
+        assert_eq!((err.kind, err.line()), (ErrorKind::UnknownParameter, 3));
+        let test = "template c(x, y)\n(\n    {x}0 => {y}\n) => Sum\nuse c(1, C)\nuse c(A, C)\n";
+        let err = Parser::new(test).parse().unwrap_err();
+        assert_eq!((err.kind, err.line(), err.column()), (ErrorKind::ExpectedCloseParen, 6, 1));
+        assert_eq!(err.note.as_deref(), Some("in template c with argument x = \"A\""));
+        assert!(err.to_string().ends_with("ERROR: Expected )\nNOTE: in template c with argument x = \"A\"\n"));
+        let test = "template e(x)\n(\n    use c(x)\n) => Sum\ntemplate c(x)\n(\n    {x}0 => C\n) => Sum\nuse e(A)\n";
+        let err = Parser::new(test).parse().unwrap_err();
+        assert_eq!(err.note.as_deref(), Some("in template c with argument x = \"x\", in template e"));
+    }
+
+    #[test]
+    fn parses_reports() {
+        let test = "
+        (
+            1000..1999 => Assets
+        ) => Sum assets
+        // closing balances
+        report balance { # bs
+            let bank = 1900..1999
+
+            (
+                @bank => Bank
+            ) => Sum bank
+            // end of balance
+        } # end
+        Check = Sum bank
+        report result {
+            (
+                3000..3999 => Sales
+            ) => Sum sales
+        }
+        ";
+
+        let document = Parser::new(test).parse_document().unwrap();
+        assert_eq!(document.items.len(), 4);
+        let balance = match &document.items[1] {
+            Item::Report(report) => report,
+            item => panic!("expected a report, got {:?}", item),
+        };
+        assert_eq!(balance.kind, ReportKind::Balance);
+        assert_eq!((balance.location.start.line, balance.location.end.column), (6, 25));
+        assert_eq!(balance.comments, vec!["closing balances"]);
+        assert_eq!(balance.comment.as_deref(), Some("bs"));
+        assert_eq!(balance.end_comments, vec!["end of balance"]);
+        assert_eq!(balance.end_comment.as_deref(), Some("end"));
+        assert!(matches!(&balance.items[..], [Item::RangeSet(_), Item::Span(_)]));
+        // the formula after the `}` isn't in the report
+        assert!(matches!(&document.items[2], Item::Formula(formula) if formula.title == "Check"));
+
+        let ast = document.into_spans();
+        assert_eq!(ast.len(), 3);
+        assert_eq!(ast[0].report, None);
+        assert_eq!(ast[1].report, Some(ReportKind::Balance));
+        assert_eq!(ast[1].formulas[0].title, "Check");
+        assert_eq!(ast[2].report, Some(ReportKind::Result));
+
+        // an included file keeps its own reports
+        let loader = MemoryLoader::new().file("equity.qa", "(\n    2000 => Equity\n) => Sum\nreport opening {\n(\n    2000 => Equity\n) => Sum\n}\n");
+        let ast = Parser::new("report balance {\ninclude \"equity.qa\"\n}\n").loader(loader.clone()).parse().unwrap();
+        assert_eq!(ast[0].report, Some(ReportKind::Balance));
+        assert_eq!(ast[1].report, Some(ReportKind::Opening));
+        // but a file included in a block can't have any
+        let err = Parser::new("(\n    include \"equity.qa\"\n) => Sum\n").loader(loader).parse().unwrap_err();
+        assert_eq!((err.kind, err.file.as_deref(), err.line()), (ErrorKind::ExpectedBlock, Some("equity.qa"), 4));
+
+        let test = "report balance {\nreport result {\n}\nreport cash {\n}\n#[bold]\n}\nreport opening {\nX = 1\n";
+        assert_eq!(errors(test), vec![
+            (ErrorKind::NestedReport, 2, 1),
+            (ErrorKind::InvalidReportKind, 4, 8),
+            (ErrorKind::UnexpectedSyntax, 5, 1),
+            (ErrorKind::DanglingAttributes, 6, 1),
+            (ErrorKind::UnexpectedSyntax, 7, 1),
+            (ErrorKind::FormulaBeforeBlock, 9, 1),
+            (ErrorKind::UnclosedReport, 8, 1),
+        ]);
+    }
+
+    #[test]
+    fn reports_structured_err() {
+        let test = "(\n    6000..6010 => Leasing\n    6020.6100 => Office Supplies\n) => Sum";
+
+        let mut parser = Parser::new(test);
+        let err = parser.parse().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidRangeSyntax);
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.column(), 10);
+        assert_eq!(err.offset(), 37);
+        assert_eq!(err.source_line, "    6020.6100 => Office Supplies");
+    }
+
+    #[test]
+    fn records_locations() {
+        let test = "Sales (\n    3010..3010 => Webshop  \n) => Sum sales\n";
+
+        let mut parser = Parser::new(test);
+        let ast = parser.parse().unwrap();
+        let span = &ast[0];
+
+        assert_eq!(span.location.start, Position { line: 1, column: 1, offset: 0 });
+        assert_eq!(span.location.end, Position { line: 1, column: 8, offset: 7 });
+
+        let range = &span.ranges[0];
+        assert_eq!(range.location.start, Position { line: 2, column: 5, offset: 12 });
+        assert_eq!(range.location.end, Position { line: 2, column: 26, offset: 33 });
+
+        assert_eq!(span.sum_location.start, Position { line: 3, column: 1, offset: 36 });
+        assert_eq!(span.sum_location.end, Position { line: 3, column: 15, offset: 50 });
+    }
+
+    #[test]
+    fn reports_all_errors_when_recovering() {
+        let test = "
+        Other costs (
+            6000..6010 => Leasing
+            (
+                6020.6100 => Office Supplies
+                6100..6200 => Consumables
+            ) => Sum miscellaneous costs
+        ) == Sum other costs
+
+        (
+            7000..7100 => Travel
+            7100..7200 = Cars
+        ) => Sum travel
+        ";
+
+        let mut parser = Parser::new(test);
+        let (ast, errors) = parser.parse_recovering();
+
+        let errors: Vec<_> = errors.iter().map(|e| (e.kind, e.line(), e.column())).collect();
+        assert_eq!(errors, vec![
+            (ErrorKind::InvalidRangeSyntax, 5, 22),
+            (ErrorKind::ExpectedArrow, 8, 12),
+            (ErrorKind::InvalidSyntaxAfterEq, 12, 25),
+        ]);
+
+        assert_eq!(ast.len(), 2);
+        assert_eq!(ast[0].ranges.len(), 1);
+        assert_eq!(ast[0].subspans[0].ranges.len(), 1);
+        assert_eq!(ast[0].subspans[0].ranges[0].title, "Consumables");
+        assert_eq!(ast[1].ranges.len(), 1);
+        assert!(matches!(&ast[1].sum_type, SumType::SumTotal { label: Some(name), .. } if name == "Sum travel"));
+    }
+
+    #[test]
+    fn reports_unclosed_block() {
+        let test = "Sales (\n    3010..3010 => Webshop\n";
+
+        let mut parser = Parser::new(test);
+        let err = parser.parse().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::ExpectedCloseParen);
+        assert_eq!(err.line(), 3);
+    }
+
+    #[test]
+    fn keeps_long_accounts() {
+        let test = "(\n    3000..99999999999 => Sales\n) => Sum";
+
+        let mut parser = Parser::new(test);
+        let ast = parser.parse().unwrap();
+        assert_eq!(ast[0].ranges[0].accounts[0].to.as_str(), "99999999999");
+    }
+
+    #[test]
+    fn reports_deep_nesting() {
+        let test = "(".repeat(MAX_NESTING + 1);
+
+        let mut parser = Parser::new(&test);
+        let err = parser.parse().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NestingTooDeep);
+
+        // we can't go on after it, but the spans before it are kept
+        let test = format!("(\n    3000 => Sales\n) => Sum sales\n{}", test);
+        let (ast, errors) = Parser::new(&test).parse_recovering();
+        assert_eq!(ast.len(), 1);
+        assert_eq!(ast[0].ranges[0].title, "Sales");
+        assert_eq!(errors.iter().map(|e| (e.kind, e.line())).collect::<Vec<_>>(), vec![(ErrorKind::NestingTooDeep, 4)]);
+
+        // the blocks an error is in don't count any more after it
+        let mut parser = Parser::new("(\n    (\n        3000.4000 => A\n    ) =>\n) => Sum");
+        assert_eq!(parser.parse().unwrap_err().kind, ErrorKind::InvalidRangeSyntax);
+        assert_eq!(parser.depth, 0);
+    }
+
+    #[test]
+    fn fuzz_regressions_dont_panic() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/regressions");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let input = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            if let Ok(document) = Parser::new(&input).parse_document() {
+                assert_eq!(Parser::new(&format_document(&document)).parse_document().unwrap(), document);
+            }
+            let _ = Parser::new(&input).parse_recovering();
+        }
+    }
+
+    #[test]
+    fn random_input_doesnt_panic() {
+        const ALPHABET: &[char] = &[
+            '(', ')', '=', '>', '.', ' ', '\n', '\r', '\t', '0', '1', '9', 'a', 'Z', '٣', 'é', '#',
+            '/', '!', ',', '*', 'x', '-', '+', '[', ']', '"',
+        ];
+
+        // a simple xorshift so we don't need a dependency on rand
+        let mut state: u32 = 0x9e37_79b9;
+        let mut rand = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+
+        for _ in 0..10_000 {
+            let len = rand() % 64;
+            let input: String = (0..len).map(|_| ALPHABET[rand() % ALPHABET.len()]).collect();
+            for syntax in &[AccountSyntax::Digits, AccountSyntax::Alphanumeric] {
+                let parser = || Parser::new(&input).account_syntax(*syntax);
+                if let Ok(spans) = parser().parse() {
+                    let formatted = format(&spans);
+                    assert_eq!(Parser::new(&formatted).account_syntax(*syntax).parse().unwrap(), spans);
+                }
+                let _ = parser().parse_recovering();
+            }
+        }
+    }
+
+    #[test]
+    fn parses_comments() {
+        let test = "
+        # Sales (all of them)
+        Sales ( // header
+            3010..3010 => Webshop # the new one
+            // moved 3050 here after 2019 audit
+            3020..4000 => Other sales
+            (
+                // nothing yet
+            ) => Sum nothing
+            # end
+        ) => Sum sales // sum
+
+        // the end
+        ";
+
+        let mut parser = Parser::new(test);
+        let ast = parser.parse().unwrap();
+        assert_eq!(ast.len(), 1);
+
+        let span = &ast[0];
+        assert_eq!(span.name.as_deref(), Some("Sales"));
+        assert_eq!(span.comments, vec!["Sales (all of them)"]);
+        assert_eq!(span.header_comment.as_deref(), Some("header"));
+        assert_eq!(span.ranges[0].title, "Webshop");
+        assert_eq!(span.ranges[0].comment.as_deref(), Some("the new one"));
+        assert_eq!(span.ranges[1].comments, vec!["moved 3050 here after 2019 audit"]);
+        assert_eq!(span.subspans[0].end_comments, vec!["nothing yet"]);
+        assert_eq!(span.end_comments, vec!["end"]);
+        assert!(matches!(&span.sum_type, SumType::SumTotal { label: Some(name), .. } if name == "Sum sales"));
+        assert_eq!(span.sum_comment.as_deref(), Some("sum"));
+        assert_eq!(span.trailing_comments, vec!["the end"]);
+
+        let ast = Parser::new("(\n    3000 => Account #5\n    3001 => Account#6 # six\n    #7\n) => Sum #8 #\n").parse().unwrap();
+        assert_eq!(ast[0].ranges[0].title, "Account #5");
+        assert_eq!(ast[0].ranges[0].comment, None);
+        assert_eq!(ast[0].ranges[1].title, "Account#6");
+        assert_eq!(ast[0].ranges[1].comment.as_deref(), Some("six"));
+        assert_eq!(ast[0].end_comments, vec!["7"]);
+        assert!(matches!(&ast[0].sum_type, SumType::SumTotal { label: Some(name), .. } if name == "Sum #8"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_to_json() {
+        let ast = Parser::new(TEST).parse().unwrap();
+        let json = serde_json::to_value(&ast).unwrap();
+
+        assert_eq!(json[0]["name"], "Sales");
+        assert_eq!(json[0]["ranges"][1]["accounts"][0]["from"], "3010");
+        assert_eq!(json[0]["sum_type"], serde_json::json!({ "kind": "sum_total", "label": "Sum sales" }));
+        assert_eq!(json[3]["subspans"][0]["sum_type"]["kind"], "sub_total");
+
+        let deserialized: Vec<Span> = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, ast);
+
+        // locations and comments are optional
+        let minimal = r#"[{ "name": null, "ranges": [{ "title": "Sales", "accounts": [{ "from": "0300", "to": 3999 }] }],
+            "sum_type": { "kind": "sum_total", "label": null } }]"#;
+        let spans: Vec<Span> = serde_json::from_str(minimal).unwrap();
+        assert_eq!(spans[0].ranges[0].accounts[0].from.as_str(), "0300");
+        assert_eq!(spans[0].ranges[0].accounts[0].to, 3999.into());
+
+        let invalid = minimal.replace("\"0300\"", "\"03 00\"");
+        assert!(serde_json::from_str::<Vec<Span>>(&invalid).is_err());
+
+        let ast = Parser::new("(\n    #[bold]\n    3000 => Sales\n) => Sum sales | nb: Sum salg\nHalf = -Sum sales / 2.5\n").parse().unwrap();
+        let json = serde_json::to_value(&ast).unwrap();
+        assert_eq!(json[0]["ranges"][0]["attributes"], serde_json::json!({ "bold": "true" }));
+        assert_eq!(json[0]["sum_type"], serde_json::json!({ "kind": "sum_total", "label": "Sum sales", "translations": { "nb": "Sum salg" } }));
+        assert_eq!(json[0]["formulas"][0]["expr"], serde_json::json!({
+            "kind": "binary",
+            "operator": "divide",
+            "left": { "kind": "negate", "operand": { "kind": "reference", "name": "Sum sales" } },
+            "right": { "kind": "number", "value": "2.50" },
+        }));
+        let deserialized: Vec<Span> = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, ast);
+
+        let test = "let bank = 1900\nreport balance {\n(\n    @bank => Bank\n) => Sum bank\n}\nTwice = Sum bank * 2\n";
+        let document = Parser::new(test).parse_document().unwrap();
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json["items"][0]["kind"], "range_set");
+        assert_eq!((&json["items"][1]["kind"], &json["items"][1]["report"]), (&"report".into(), &"balance".into()));
+        assert_eq!(json["items"][1]["items"][0]["kind"], "span");
+        assert_eq!((&json["items"][2]["kind"], &json["items"][2]["title"]), (&"formula".into(), &"Twice".into()));
+        let deserialized: Document = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, document);
+    }
 }
@@ -0,0 +1,132 @@
+//! Loading the files included with `include "path.qa"`.
+//!
+//! The parser only reads included files if it's given a `Loader`. `FileLoader` reads them from
+//! the file system and `MemoryLoader` from a map, which is handy for tests and for definitions
+//! stored somewhere else than in files.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Finds and reads included files. A loader is shared by the parsers of the included files and
+/// the parser can be sent to other threads, so it has to be `Send` and `Sync`.
+pub trait Loader: Send + Sync {
+    /// Returns the name of the file `path` refers to when it's included from the file `from`, or
+    /// from the input of the parser if `from` is `None`. Two includes of the same name are the
+    /// same file, which is how circular includes are found. The name is used in error messages.
+    ///
+    /// By default the path is the name.
+    fn resolve(&self, path: &str, from: Option<&str>) -> String {
+        let _ = from;
+        path.to_string()
+    }
+
+    /// Reads the file with a name returned by `resolve`.
+    fn load(&self, name: &str) -> io::Result<String>;
+}
+
+/// Reads included files from the file system. A relative path is relative to the directory of
+/// the file with the include, or to the current directory for the input of the parser if it
+/// hasn't got a file name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileLoader;
+
+impl FileLoader {
+    pub fn new() -> Self {
+        FileLoader
+    }
+}
+
+impl Loader for FileLoader {
+    fn resolve(&self, path: &str, from: Option<&str>) -> String {
+        let dir = from.and_then(|from| Path::new(from).parent()).unwrap_or_else(|| Path::new(""));
+        normalize(&dir.join(path)).to_string_lossy().into_owned()
+    }
+
+    fn load(&self, name: &str) -> io::Result<String> {
+        std::fs::read_to_string(name)
+    }
+}
+
+/// Removes the `.` and `..` in the path so `shared/../equity.qa` and `equity.qa` are the same
+/// file. Symbolic links aren't followed.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Reads included files from a map of file names to their text. Paths aren't resolved, an
+/// include has to use the name the file was added with.
+///
+/// ```rust, ignore
+/// let equity = "Equity (\n    2000..2099 => Equity\n) => Sum equity\n";
+/// let loader = MemoryLoader::new().file("equity.qa", equity);
+/// let spans = Parser::new("include \"equity.qa\"\n").loader(loader).parse()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLoader {
+    files: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        MemoryLoader::default()
+    }
+
+    /// Adds a file, replacing any file with the same name.
+    pub fn file(mut self, name: &str, text: &str) -> Self {
+        self.files.insert(name.to_string(), text.to_string());
+        self
+    }
+}
+
+impl Loader for MemoryLoader {
+    fn load(&self, name: &str) -> io::Result<String> {
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", name)))
+    }
+}
+
+/// The loader of a parser, shared with the parsers of the files it includes.
+#[derive(Clone)]
+pub(crate) struct SharedLoader(pub(crate) Arc<dyn Loader>);
+
+impl fmt::Debug for SharedLoader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Loader")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_to_the_including_file() {
+        let loader = FileLoader::new();
+        let path = |p: &str| PathBuf::from(p).to_string_lossy().into_owned();
+        assert_eq!(loader.resolve("equity.qa", None), path("equity.qa"));
+        assert_eq!(loader.resolve("equity.qa", Some("reports/acme.qa")), path("reports/equity.qa"));
+        assert_eq!(loader.resolve("../shared/./equity.qa", Some("reports/acme.qa")), path("shared/equity.qa"));
+        assert_eq!(loader.resolve("../equity.qa", Some("acme.qa")), path("../equity.qa"));
+        assert_eq!(loader.resolve("/shared/equity.qa", Some("reports/acme.qa")), path("/shared/equity.qa"));
+
+        let loader = MemoryLoader::new().file("equity.qa", "text");
+        assert_eq!(loader.resolve("equity.qa", Some("reports/acme.qa")), "equity.qa");
+        assert_eq!(loader.load("equity.qa").unwrap(), "text");
+        assert_eq!(loader.load("other.qa").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
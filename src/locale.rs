@@ -0,0 +1,170 @@
+//! Titles in more than one language.
+//!
+//! A header, range title, sum label or formula title can have translations after it, like
+//! `Salg | en: Sales | de: Umsatz`. The text before the first `|` is the title in the language the
+//! definition is written in, the translations are kept by their locale.
+
+use std::collections::BTreeMap;
+
+use crate::{Formula, Range, Span, SumType};
+
+/// The translations of a title by locale, like `en` or `en-GB`.
+pub type Translations = BTreeMap<String, String>;
+
+/// Picks the text for the first of the `locales` the title is translated to, or the title itself
+/// if it isn't translated to any of them.
+///
+/// A locale with a region like `en-GB` falls back to the language `en` before the next locale is
+/// tried. Locales are compared without case, and `_` is the same as `-`.
+pub fn localize<'a, S: AsRef<str>>(title: &'a str, translations: &'a Translations, locales: &[S]) -> &'a str {
+    for locale in locales {
+        let locale = locale.as_ref();
+        if let Some(text) = lookup(translations, locale) {
+            return text;
+        }
+
+        // en-GB falls back to en
+        if let Some((language, _)) = locale.split_once(['-', '_']) {
+            if let Some(text) = lookup(translations, language) {
+                return text;
+            }
+        }
+    }
+
+    title
+}
+
+fn lookup<'a>(translations: &'a Translations, locale: &str) -> Option<&'a str> {
+    let same = |a: &str, b: &str| {
+        a.len() == b.len()
+            && a.chars().zip(b.chars()).all(|(a, b)| {
+                a.eq_ignore_ascii_case(&b) || (matches!(a, '-' | '_') && matches!(b, '-' | '_'))
+            })
+    };
+
+    translations
+        .iter()
+        .find(|(key, _)| same(key, locale))
+        .map(|(_, text)| text.as_str())
+}
+
+impl Span {
+    /// The header in the first of the `locales` it's translated to, see `localize`.
+    pub fn localized_name<S: AsRef<str>>(&self, locales: &[S]) -> Option<&str> {
+        let name = self.name.as_deref()?;
+        Some(localize(name, &self.name_translations, locales))
+    }
+}
+
+impl SumType {
+    /// The label of the sum in the first of the `locales` it's translated to, see `localize`.
+    pub fn localized_name<S: AsRef<str>>(&self, locales: &[S]) -> Option<&str> {
+        let name = self.name()?;
+        Some(localize(name, self.translations(), locales))
+    }
+}
+
+impl Range {
+    /// The title in the first of the `locales` it's translated to, see `localize`.
+    pub fn localized_title<S: AsRef<str>>(&self, locales: &[S]) -> &str {
+        localize(&self.title, &self.translations, locales)
+    }
+}
+
+impl Formula {
+    /// The title in the first of the `locales` it's translated to, see `localize`.
+    pub fn localized_title<S: AsRef<str>>(&self, locales: &[S]) -> &str {
+        localize(&self.title, &self.translations, locales)
+    }
+}
+
+/// Returns the number of characters in the `| locale:` that starts a translation if `chars`
+/// starts with one, like `| en:` or `|nb-NO:`.
+///
+/// The locale has to start with a language of two or three lowercase letters, so titles like
+/// `Cash | note: 7` or `Taxes | VAT: 25%` aren't split.
+pub(crate) fn translation_start(mut chars: impl Iterator<Item = char>) -> Option<usize> {
+    if chars.next()? != '|' {
+        return None;
+    }
+
+    let mut len = 1;
+    let mut next = || {
+        len += 1;
+        chars.next()
+    };
+
+    let mut c = next()?;
+    while c == ' ' || c == '\t' {
+        c = next()?;
+    }
+
+    let mut language = 0;
+    while c.is_ascii_lowercase() {
+        language += 1;
+        c = next()?;
+    }
+    if !(2..=3).contains(&language) {
+        return None;
+    }
+
+    // a script or region like -Hant or _NO
+    while c == '-' || c == '_' {
+        c = next()?;
+        let mut subtag = 0;
+        while c.is_ascii_alphanumeric() {
+            subtag += 1;
+            c = next()?;
+        }
+        if subtag == 0 {
+            return None;
+        }
+    }
+
+    match c {
+        ':' => Some(len),
+        _ => None,
+    }
+}
+
+/// Returns true if there is something in the text that would be read as the start of a
+/// translation.
+pub(crate) fn has_translation_start(text: &str) -> bool {
+    text.char_indices()
+        .any(|(i, c)| c == '|' && translation_start(text[i..].chars()).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_language_and_title() {
+        let translations: Translations = [("en", "Sales"), ("nb_NO", "Salg"), ("de-AT", "Umsatz")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        assert_eq!(localize("Omsetning", &translations, &["en"]), "Sales");
+        assert_eq!(localize("Omsetning", &translations, &["en-GB"]), "Sales");
+        assert_eq!(localize("Omsetning", &translations, &["NB-no"]), "Salg");
+        assert_eq!(localize("Omsetning", &translations, &["de", "en"]), "Sales");
+        assert_eq!(localize("Omsetning", &translations, &["fr"]), "Omsetning");
+        assert_eq!(localize("Omsetning", &translations, &[] as &[&str]), "Omsetning");
+    }
+
+    #[test]
+    fn finds_translation_starts() {
+        assert_eq!(translation_start("| en: Sales".chars()), Some(5));
+        assert_eq!(translation_start("|nb-NO:".chars()), Some(7));
+        assert_eq!(translation_start("| zh-Hant-TW:x".chars()), Some(13));
+        assert_eq!(translation_start("| note: 7".chars()), None);
+        assert_eq!(translation_start("| VAT: 25%".chars()), None);
+        assert_eq!(translation_start("| En: Sales".chars()), None);
+        assert_eq!(translation_start("| en".chars()), None);
+        assert_eq!(translation_start("| en-: x".chars()), None);
+        assert_eq!(translation_start("en: x".chars()), None);
+        assert!(has_translation_start("A | B | en: C"));
+        assert!(!has_translation_start("A | B"));
+    }
+}
@@ -0,0 +1,19 @@
+/// A position in the source text. `line` and `column` are 1-based and counted in characters,
+/// `offset` is the 0-based byte offset into the input string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+/// The part of the source text a node in the syntax tree was parsed from. `start` points at the
+/// first character and `end` points just past the last character (trailing whitespace and line
+/// breaks are not included).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    pub start: Position,
+    pub end: Position,
+}
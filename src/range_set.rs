@@ -0,0 +1,69 @@
+//! Range sets like `let bank = 1900..1999, !1950` that range lines use as `@bank`.
+//!
+//! A set can be used before it's defined and in another file than the one defining it, so the
+//! parser keeps the names where they're used and the sets are resolved when the whole input is
+//! parsed.
+
+use std::collections::HashMap;
+
+use crate::{AccountRange, Range, RangeSet, SetReference, Span};
+
+/// Puts the accounts of the range sets in the account lists of the ranges that use them. A name
+/// used without a set keeps the place it's used at with no accounts, `validate_document` reports
+/// it.
+///
+/// `Parser::parse` does this for you with the sets of the input, call it again if you change the
+/// range sets or build the spans yourself. The accounts of an earlier resolve are replaced, so it
+/// can be called more than once.
+pub fn resolve_range_sets(spans: &mut [Span], sets: &[RangeSet]) {
+    let sets = collect(sets);
+    for span in spans.iter_mut() {
+        resolve_span(span, &sets);
+    }
+}
+
+/// The accounts of every range set by name, the first one if a name is defined twice.
+pub(crate) fn collect<'a>(sets: impl IntoIterator<Item = &'a RangeSet>) -> HashMap<String, Vec<AccountRange>> {
+    let mut accounts = HashMap::new();
+    for set in sets {
+        accounts.entry(set.name.clone()).or_insert_with(|| set.accounts.clone());
+    }
+    accounts
+}
+
+pub(crate) fn resolve_span(span: &mut Span, sets: &HashMap<String, Vec<AccountRange>>) {
+    for range in &mut span.ranges {
+        resolve_range(range, sets);
+    }
+    for subspan in &mut span.subspans {
+        resolve_span(subspan, sets);
+    }
+}
+
+fn resolve_range(range: &mut Range, sets: &HashMap<String, Vec<AccountRange>>) {
+    if range.sets.is_empty() {
+        return;
+    }
+
+    let written = std::mem::take(&mut range.accounts);
+    let mut written = written.into_iter();
+    let mut next = 0;
+
+    for reference in &mut range.sets {
+        // the accounts written before the set, and the accounts of the set from the last resolve
+        let start = reference.start.max(next);
+        range.accounts.extend(written.by_ref().take(start - next));
+        written.by_ref().take(reference.len).for_each(drop);
+        next = start + reference.len;
+
+        let accounts = sets.get(&reference.name).map(Vec::as_slice).unwrap_or_default();
+        *reference = SetReference {
+            name: std::mem::take(&mut reference.name),
+            start: range.accounts.len(),
+            len: accounts.len(),
+        };
+        range.accounts.extend_from_slice(accounts);
+    }
+
+    range.accounts.extend(written);
+}
@@ -0,0 +1,337 @@
+//! Renders an evaluated report as plain text.
+//!
+//! The layout is the one shown in the crate documentation. The header of a span is written in
+//! upper case, the ranges are listed with their amounts, a line of `-` goes under the ranges and
+//! a line of `=` under the total of a top level span:
+//!
+//! ```text
+//! OTHER COSTS
+//! Leasing                         1,200.00
+//!   Office supplies       300.00
+//!   Consumables           150.00
+//!   ----------------------------
+//! Sum misc. costs                   450.00
+//! ----------------------------------------
+//! Sum other costs                 1,650.00
+//! ========================================
+//! ```
+//!
+//! Nested spans are indented and their amounts are moved one amount column to the left, so the
+//! sub total ends up in the same column as the lines of the parent span.
+//!
+//! A formula like `Gross profit = Sum sales - Sum material` is written after its span like the
+//! total of a top level span, with the amount left out if it can't be computed.
+//!
+//! Titles are written in the first of the `locales` they're translated to, see `localize`.
+
+use crate::eval::{EvaluatedFormula, EvaluatedSpan};
+use crate::Amount;
+
+/// How amounts are aligned inside the amount column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Options for the text layout.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The width of the lines of a top level span.
+    pub width: usize,
+    /// The width of the column the amounts are written in.
+    pub amount_width: usize,
+    pub amount_align: Align,
+    /// The number of decimals to round the amounts to.
+    pub decimals: u32,
+    pub thousands_separator: Option<char>,
+    pub decimal_point: char,
+    /// The number of spaces to indent each level of nesting.
+    pub indent: usize,
+    /// The locales to write the titles in, like `["nb-NO", "en"]`. The first one a title is
+    /// translated to is used, and the title as it's written if there are none.
+    pub locales: Vec<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: 60,
+            amount_width: 15,
+            amount_align: Align::Right,
+            decimals: 2,
+            thousands_separator: Some(','),
+            decimal_point: '.',
+            indent: 2,
+            locales: vec![],
+        }
+    }
+}
+
+/// Renders the report with the default options.
+pub fn render(spans: &[EvaluatedSpan]) -> String {
+    render_with(spans, &RenderOptions::default())
+}
+
+/// Renders the report with the given options.
+pub fn render_with(spans: &[EvaluatedSpan], options: &RenderOptions) -> String {
+    let mut renderer = Renderer {
+        options,
+        out: String::new(),
+    };
+
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            renderer.out.push('\n');
+        }
+        renderer.span(span, 0);
+
+        for formula in &span.formulas {
+            renderer.out.push('\n');
+            renderer.formula(formula);
+        }
+    }
+
+    renderer.out
+}
+
+struct Renderer<'a> {
+    options: &'a RenderOptions,
+    out: String,
+}
+
+impl Renderer<'_> {
+    fn span(&mut self, span: &EvaluatedSpan, level: usize) {
+        let locales = &self.options.locales;
+        if let Some(name) = span.span.localized_name(locales) {
+            let indent = self.indent(level);
+            self.push_line(format!("{}{}", indent, name.to_uppercase()));
+        }
+
+        for range in &span.ranges {
+            self.amount_line(level, range.range.localized_title(locales), Some(range.amount));
+        }
+
+        for subspan in &span.subspans {
+            self.span(subspan, level + 1);
+        }
+
+        let sum_name = span.span.sum_type.localized_name(locales).unwrap_or("");
+
+        self.rule(level, '-');
+        // the sum of a nested span is a line in the parent span
+        self.amount_line(level.saturating_sub(1), sum_name, Some(span.total));
+        if level == 0 {
+            self.rule(level, '=');
+        }
+    }
+
+    fn formula(&mut self, formula: &EvaluatedFormula) {
+        let title = formula.formula.localized_title(&self.options.locales);
+        self.amount_line(0, title, formula.amount);
+        self.rule(0, '=');
+    }
+
+    fn amount_line(&mut self, level: usize, label: &str, amount: Option<Amount>) {
+        let options = self.options;
+        let indent = self.indent(level);
+        let amount = match amount {
+            Some(amount) => amount.format(options.decimals, options.thousands_separator, options.decimal_point),
+            None => String::new(),
+        };
+
+        let label_width = self
+            .line_width(level)
+            .saturating_sub(indent.len() + options.amount_width);
+        let label_len = label.chars().count();
+        let padding = if label_len < label_width {
+            label_width - label_len
+        } else {
+            1
+        };
+
+        let amount = match options.amount_align {
+            Align::Right => format!("{:>width$}", amount, width = options.amount_width),
+            Align::Left => format!("{:<width$}", amount, width = options.amount_width),
+        };
+
+        let line = format!("{}{}{}{}", indent, label, " ".repeat(padding), amount);
+        self.push_line(line.trim_end().to_string());
+    }
+
+    fn rule(&mut self, level: usize, c: char) {
+        let indent = self.indent(level);
+        let len = self.line_width(level).saturating_sub(indent.len());
+        let line = format!("{}{}", indent, c.to_string().repeat(len));
+        self.push_line(line);
+    }
+
+    fn indent(&self, level: usize) -> String {
+        " ".repeat(level * self.options.indent)
+    }
+
+    /// Nested spans have their amounts one amount column further to the left.
+    fn line_width(&self, level: usize) -> usize {
+        let options = self.options;
+        let min = level * options.indent + options.amount_width;
+        options
+            .width
+            .saturating_sub(level * options.amount_width)
+            .max(min)
+    }
+
+    fn push_line(&mut self, line: String) {
+        self.out.push_str(&line);
+        self.out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{evaluate, parse_balances};
+    use crate::Parser;
+
+    #[test]
+    fn renders_documented_layout() {
+        let test = "
+        Sales (
+            3010..3010 => Webshop
+        ) => Sum sales
+
+        Other costs (
+            6000..6010 => Leasing
+            (
+                6020..6100 => Office supplies
+                6100..6200 => Consumables
+            ) => Sum misc. costs
+        ) => Sum other costs
+        ";
+
+        let balances = parse_balances("3010,-12345.5\n6000,1200\n6020,300\n6150,150\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let options = RenderOptions {
+            width: 40,
+            amount_width: 10,
+            ..RenderOptions::default()
+        };
+
+        let expected = "\
+SALES
+Webshop                       -12,345.50
+----------------------------------------
+Sum sales                     -12,345.50
+========================================
+
+OTHER COSTS
+Leasing                         1,200.00
+  Office supplies       300.00
+  Consumables           150.00
+  ----------------------------
+Sum misc. costs                   450.00
+----------------------------------------
+Sum other costs                 1,650.00
+========================================
+";
+        assert_eq!(render_with(&report, &options), expected);
+    }
+
+    #[test]
+    fn renders_formulas() {
+        let test = "
+        (
+            3000 => Sales
+        ) => Sum sales
+        (
+            4000 => Material
+        ) => Sum material
+
+        Gross profit = Sum sales - Sum material
+        Margin = Gross profit / 0
+        ";
+
+        let balances = parse_balances("3000,1000\n4000,250\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let options = RenderOptions {
+            width: 30,
+            amount_width: 10,
+            ..RenderOptions::default()
+        };
+
+        let expected = "\
+Material                250.00
+------------------------------
+Sum material            250.00
+==============================
+
+Gross profit            750.00
+==============================
+
+Margin
+==============================
+";
+        assert!(render_with(&report, &options).ends_with(expected));
+    }
+
+    #[test]
+    fn renders_translations() {
+        let test = "
+        Salg | en: Sales (
+            3000 => Webshop | en-GB: Web shop
+            3100 => Service
+        ) => Sum salg | en: Sum sales
+        Brutto | de: Rohertrag = Sum salg
+        ";
+
+        let balances = parse_balances("3000,100\n3100,50\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let options = RenderOptions {
+            width: 30,
+            amount_width: 10,
+            locales: vec!["en-GB".to_string(), "de".to_string()],
+            ..RenderOptions::default()
+        };
+
+        let expected = "\
+SALES
+Web shop                100.00
+Service                  50.00
+------------------------------
+Sum sales               150.00
+==============================
+
+Rohertrag               150.00
+==============================
+";
+        assert_eq!(render_with(&report, &options), expected);
+    }
+
+    #[test]
+    fn respects_number_format_and_alignment() {
+        let test = "(\n    3000..3999 => A very long title that does not fit\n) =>\n";
+        let balances = parse_balances("3000,1234567.891\n").unwrap();
+        let spans = Parser::new(test).parse().unwrap();
+        let report = evaluate(&spans, &balances).unwrap();
+
+        let options = RenderOptions {
+            width: 30,
+            amount_width: 14,
+            amount_align: Align::Left,
+            decimals: 1,
+            thousands_separator: Some(' '),
+            decimal_point: ',',
+            indent: 4,
+            locales: vec![],
+        };
+
+        let rendered = render_with(&report, &options);
+        let first = rendered.lines().next().unwrap();
+        assert_eq!(first, "A very long title that does not fit 1 234 567,9");
+    }
+}
@@ -0,0 +1,782 @@
+//! Semantic validation of a parsed report definition.
+//!
+//! The parser only checks the syntax, so `4000..3000 => Sales` or two spans claiming the same
+//! accounts parse just fine. `validate` walks the syntax tree and reports these problems together
+//! with where they were defined:
+//!
+//! ```rust, ignore
+//! let spans = Parser::new(input).parse()?;
+//! for diagnostic in validate(&spans) {
+//!     println!("{}", diagnostic);
+//! }
+//! ```
+//!
+//! The range sets and templates aren't spans, so the rules about them are checked by
+//! `validate_document`:
+//!
+//! ```rust, ignore
+//! let document = Parser::new(input).parse_document()?;
+//! let diagnostics = validate_document(&document);
+//! ```
+//!
+//! Use a `Validator` if you want to turn rules off or change their severity.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::error::write_report;
+use crate::formatter::range_part;
+use crate::{AccountId, AccountPattern, AccountRange, Document, Formula, Item, Location, Range, RangeSet, Span, SumType, Template};
+
+/// How serious a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// The checks the validator can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// A range where `from` is larger than `to`, like `4000..3000` or `!4000..3000`.
+    ReversedRange,
+    /// Two ranges in the same top level span (including its subspans) share accounts.
+    OverlapInSpan,
+    /// Two ranges in different top level spans share accounts.
+    OverlapAcrossSpans,
+    /// A span without any ranges or subspans.
+    EmptySpan,
+    /// A span without a label after `) =>`.
+    MissingSumLabel,
+    /// Two spans with the same title, two ranges in the same span with the same title, a formula
+    /// with the same title as a sum label or another formula, or two range sets or templates with
+    /// the same name. The range sets and templates are only checked by `validate_document`.
+    DuplicateTitle,
+    /// A formula refers to a name that isn't a sum label or the title of a formula.
+    UnknownReference,
+    /// A formula refers to itself, directly or through other formulas.
+    CircularReference,
+    /// A range line uses a range set like `@bank` that isn't defined with `let`. Only checked by
+    /// `validate_document`.
+    UndefinedRangeSet,
+    /// A range set is defined with `let` but no range line uses it. Only checked by
+    /// `validate_document`.
+    UnusedRangeSet,
+}
+
+impl Rule {
+    /// All the rules, in the order they're checked.
+    pub const ALL: [Rule; 10] = [
+        Rule::ReversedRange,
+        Rule::OverlapInSpan,
+        Rule::OverlapAcrossSpans,
+        Rule::EmptySpan,
+        Rule::MissingSumLabel,
+        Rule::DuplicateTitle,
+        Rule::UnknownReference,
+        Rule::CircularReference,
+        Rule::UndefinedRangeSet,
+        Rule::UnusedRangeSet,
+    ];
+
+    /// The severity a rule reports with unless it's changed on the `Validator`.
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Rule::ReversedRange
+            | Rule::UnknownReference
+            | Rule::CircularReference
+            | Rule::UndefinedRangeSet => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// A single finding from the validator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the problem is.
+    pub location: Location,
+    /// Another place involved in the problem, like the first of two overlapping ranges.
+    pub related: Option<Location>,
+    /// The file the problem is in if it's in a span included from another file, see `Span::file`.
+    pub file: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} (", self.severity, self.message)?;
+        if let Some(file) = &self.file {
+            write!(f, "file: {}, ", file)?;
+        }
+        write!(f, "line: {}, pos: {})", self.location.start.line, self.location.start.column)
+    }
+}
+
+impl Diagnostic {
+    /// Formats the diagnostic the same way a `ParseError` is formatted, with the source line
+    /// and a marker pointing at where the problem is. `source` is the text the spans were
+    /// parsed from, or the text of the `file` the problem is in.
+    pub fn report(&self, source: &str) -> String {
+        let position = self.location.start;
+        let source_line = source
+            .split('\n')
+            .nth(position.line.saturating_sub(1))
+            .unwrap_or("");
+        let level = self.severity.to_string().to_uppercase();
+
+        let mut text = String::new();
+        // writing to a String can't fail
+        let _ = write_report(&mut text, self.file.as_deref(), position, source_line, &level, &self.message);
+        text
+    }
+}
+
+/// Validates the spans with all rules at their default severity.
+pub fn validate(spans: &[Span]) -> Vec<Diagnostic> {
+    Validator::new().validate(spans)
+}
+
+/// Validates the document with all rules at their default severity.
+pub fn validate_document(document: &Document) -> Vec<Diagnostic> {
+    Validator::new().validate_document(document)
+}
+
+/// A configurable set of rules to validate spans with.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    rules: HashMap<Rule, Severity>,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator::new()
+    }
+}
+
+impl Validator {
+    /// Creates a validator with all rules enabled at their default severity.
+    pub fn new() -> Self {
+        let rules = Rule::ALL
+            .iter()
+            .map(|rule| (*rule, rule.default_severity()))
+            .collect();
+
+        Validator { rules }
+    }
+
+    /// Enables a rule with the given severity.
+    pub fn rule(mut self, rule: Rule, severity: Severity) -> Self {
+        self.rules.insert(rule, severity);
+        self
+    }
+
+    /// Disables a rule.
+    pub fn allow(mut self, rule: Rule) -> Self {
+        self.rules.remove(&rule);
+        self
+    }
+
+    /// Runs the enabled rules and returns the findings ordered by where they are in the source,
+    /// with the ones in included files last. The rules about range sets and templates need the
+    /// `let` and `template` lines, so they're left out.
+    pub fn validate(&self, spans: &[Span]) -> Vec<Diagnostic> {
+        let mut diagnostics = self.check_spans(spans);
+        sort(&mut diagnostics);
+        diagnostics
+    }
+
+    /// Runs the enabled rules on the spans of the document and its range sets, and returns the
+    /// findings ordered like `validate` does.
+    pub fn validate_document(&self, document: &Document) -> Vec<Diagnostic> {
+        let spans = document.spans();
+        let mut diagnostics = self.check_spans(&spans);
+        self.check_range_sets(document, &spans, &mut diagnostics);
+        self.check_templates(document, &mut diagnostics);
+        sort(&mut diagnostics);
+        diagnostics
+    }
+
+    fn check_spans(&self, spans: &[Span]) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for span in spans {
+            self.check_span(span, &mut diagnostics);
+        }
+
+        self.check_duplicate_titles(spans, &mut diagnostics);
+        self.check_formulas(spans, &mut diagnostics);
+
+        let ranges: Vec<Vec<(&Range, Option<&str>)>> = spans.iter().map(all_ranges).collect();
+        for (i, span_ranges) in ranges.iter().enumerate() {
+            for (j, (a, _)) in span_ranges.iter().enumerate() {
+                for (b, file) in &span_ranges[j + 1..] {
+                    if overlaps(a, b) {
+                        self.report(&mut diagnostics, Rule::OverlapInSpan, *file, b.location, Some(a.location), || {
+                            format!("Range {} overlaps {} ({}) in the same span", range_part(b), range_part(a), a.title)
+                        });
+                    }
+                }
+
+                for other in &ranges[i + 1..] {
+                    for (b, file) in other {
+                        if overlaps(a, b) {
+                            self.report(&mut diagnostics, Rule::OverlapAcrossSpans, *file, b.location, Some(a.location), || {
+                                format!("Range {} overlaps {} ({}) in another span", range_part(b), range_part(a), a.title)
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    fn check_span(&self, span: &Span, diagnostics: &mut Vec<Diagnostic>) {
+        let file = span.file.as_deref();
+        if span.ranges.is_empty() && span.subspans.is_empty() {
+            self.report(diagnostics, Rule::EmptySpan, file, span.location, None, || {
+                "Span has no ranges or subspans".to_string()
+            });
+        }
+
+        let sum_name = match &span.sum_type {
+            SumType::SumTotal { label, .. } | SumType::SubTotal { label, .. } => label,
+        };
+
+        if sum_name.as_ref().map(|name| name.is_empty()).unwrap_or(true) {
+            self.report(diagnostics, Rule::MissingSumLabel, file, span.sum_location, None, || {
+                "Span has no sum label".to_string()
+            });
+        }
+
+        for range in &span.ranges {
+            for accounts in range.accounts.iter().filter(|a| a.from > a.to) {
+                self.report(diagnostics, Rule::ReversedRange, file, range.location, None, || {
+                    format!("Range {} is reversed, the start is after the end", accounts)
+                });
+            }
+        }
+
+        let mut titles: HashMap<&str, Location> = HashMap::new();
+        for range in &span.ranges {
+            match titles.get(range.title.as_str()) {
+                Some(first) => {
+                    self.report(diagnostics, Rule::DuplicateTitle, file, range.location, Some(*first), || {
+                        format!("Duplicate title \"{}\" in the same span", range.title)
+                    });
+                }
+                None => {
+                    titles.insert(&range.title, range.location);
+                }
+            }
+        }
+
+        for subspan in &span.subspans {
+            self.check_span(subspan, diagnostics);
+        }
+    }
+
+    fn check_duplicate_titles(&self, spans: &[Span], diagnostics: &mut Vec<Diagnostic>) {
+        fn walk<'a>(span: &'a Span, out: &mut Vec<&'a Span>) {
+            out.push(span);
+            for subspan in &span.subspans {
+                walk(subspan, out);
+            }
+        }
+
+        let mut all = vec![];
+        for span in spans {
+            walk(span, &mut all);
+        }
+
+        let mut titles: HashMap<&str, Location> = HashMap::new();
+        for span in all {
+            let name = match &span.name {
+                Some(name) => name,
+                None => continue,
+            };
+
+            match titles.get(name.as_str()) {
+                Some(first) => {
+                    self.report(diagnostics, Rule::DuplicateTitle, span.file.as_deref(), span.location, Some(*first), || {
+                        format!("Duplicate span title \"{}\"", name)
+                    });
+                }
+                None => {
+                    titles.insert(name, span.location);
+                }
+            }
+        }
+    }
+
+    fn check_formulas(&self, spans: &[Span], diagnostics: &mut Vec<Diagnostic>) {
+        // the sum labels in the order they're written, after the labels of the subspans
+        // and the formulas with the file they're in
+        type Formulas<'a> = Vec<(&'a Formula, Option<&'a str>)>;
+        fn walk<'a>(span: &'a Span, labels: &mut Vec<(&'a str, Location)>, formulas: &mut Formulas<'a>) {
+            for subspan in &span.subspans {
+                walk(subspan, labels, formulas);
+            }
+            if let Some(label) = span.sum_type.name().filter(|label| !label.is_empty()) {
+                labels.push((label, span.sum_location));
+            }
+            formulas.extend(span.formulas.iter().map(|formula| (formula, span.file.as_deref())));
+        }
+
+        let (mut all_labels, mut all_formulas) = (vec![], vec![]);
+        for span in spans {
+            walk(span, &mut all_labels, &mut all_formulas);
+        }
+
+        let mut labels: HashMap<&str, Location> = HashMap::new();
+        for (label, location) in all_labels {
+            labels.entry(label).or_insert(location);
+        }
+
+        let mut formulas: HashMap<&str, &Formula> = HashMap::new();
+        for (formula, file) in &all_formulas {
+            let title = formula.title.as_str();
+            match labels.get(title).copied().or_else(|| formulas.get(title).map(|first| first.location)) {
+                Some(first) => {
+                    self.report(diagnostics, Rule::DuplicateTitle, *file, formula.location, Some(first), || {
+                        format!("Formula title \"{}\" is already the name of another line", title)
+                    });
+                }
+                None => {
+                    formulas.insert(title, formula);
+                }
+            }
+        }
+
+        for (formula, file) in all_formulas {
+            let mut reported = HashSet::new();
+            for name in formula.expr.references() {
+                if !labels.contains_key(name) && !formulas.contains_key(name) && reported.insert(name) {
+                    self.report(diagnostics, Rule::UnknownReference, file, formula.location, None, || {
+                        format!("\"{}\" in {} isn't a sum label or the title of a formula", name, formula.title)
+                    });
+                }
+            }
+
+            if refers_to_itself(formula, &labels, &formulas) {
+                self.report(diagnostics, Rule::CircularReference, file, formula.location, None, || {
+                    format!("Formula \"{}\" refers to itself", formula.title)
+                });
+            }
+        }
+    }
+
+    fn check_range_sets(&self, document: &Document, spans: &[Span], diagnostics: &mut Vec<Diagnostic>) {
+        // the range sets with the file they're in
+        fn walk<'a>(items: &'a [Item], file: Option<&'a str>, sets: &mut Vec<(&'a RangeSet, Option<&'a str>)>) {
+            for item in items {
+                match item {
+                    Item::RangeSet(set) => sets.push((set, file)),
+                    Item::Include(include) => walk(&include.document.items, Some(&include.file), sets),
+                    Item::Report(report) => walk(&report.items, file, sets),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut all_sets = vec![];
+        walk(&document.items, None, &mut all_sets);
+        let ranges = spans.iter().flat_map(all_ranges);
+
+        let mut sets: HashMap<&str, Location> = HashMap::new();
+        for (set, file) in &all_sets {
+            match sets.get(set.name.as_str()) {
+                Some(first) => {
+                    self.report(diagnostics, Rule::DuplicateTitle, *file, set.location, Some(*first), || {
+                        format!("Range set @{} is already defined", set.name)
+                    });
+                }
+                None => {
+                    sets.insert(&set.name, set.location);
+                }
+            }
+        }
+
+        let mut used = HashSet::new();
+        for (range, file) in ranges {
+            for reference in &range.sets {
+                used.insert(reference.name.as_str());
+                if !sets.contains_key(reference.name.as_str()) {
+                    self.report(diagnostics, Rule::UndefinedRangeSet, file, range.location, None, || {
+                        format!("Range set @{} in {} isn't defined", reference.name, range.title)
+                    });
+                }
+            }
+        }
+
+        for (set, file) in all_sets {
+            if !used.contains(set.name.as_str()) {
+                self.report(diagnostics, Rule::UnusedRangeSet, file, set.location, None, || {
+                    format!("Range set @{} isn't used", set.name)
+                });
+            }
+        }
+    }
+
+    fn check_templates(&self, document: &Document, diagnostics: &mut Vec<Diagnostic>) {
+        // the templates with the file they're in
+        fn walk<'a>(items: &'a [Item], file: Option<&'a str>, templates: &mut Vec<(&'a Template, Option<&'a str>)>) {
+            for item in items {
+                match item {
+                    Item::Template(template) => templates.push((template, file)),
+                    Item::Include(include) => walk(&include.document.items, Some(&include.file), templates),
+                    Item::Report(report) => walk(&report.items, file, templates),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut all_templates = vec![];
+        walk(&document.items, None, &mut all_templates);
+
+        let mut templates: HashMap<&str, Location> = HashMap::new();
+        for (template, file) in all_templates {
+            match templates.get(template.name.as_str()) {
+                Some(first) => {
+                    self.report(diagnostics, Rule::DuplicateTitle, file, template.location, Some(*first), || {
+                        format!("Template {} is already defined", template.name)
+                    });
+                }
+                None => {
+                    templates.insert(&template.name, template.location);
+                }
+            }
+        }
+    }
+
+    fn report<F>(
+        &self,
+        diagnostics: &mut Vec<Diagnostic>,
+        rule: Rule,
+        file: Option<&str>,
+        location: Location,
+        related: Option<Location>,
+        message: F,
+    ) where
+        F: FnOnce() -> String,
+    {
+        if let Some(severity) = self.rules.get(&rule) {
+            diagnostics.push(Diagnostic {
+                rule,
+                severity: *severity,
+                message: message(),
+                location,
+                related,
+                file: file.map(str::to_string),
+            });
+        }
+    }
+}
+
+/// Orders the diagnostics by where they are in the source, with the ones in included files last.
+fn sort(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| a.file.cmp(&b.file).then(a.location.start.offset.cmp(&b.location.start.offset)));
+}
+
+/// Follows the references of the formula through other formulas to see if it gets back to it. A
+/// reference to a sum label ends there since it counts before a formula with the same title.
+fn refers_to_itself(formula: &Formula, labels: &HashMap<&str, Location>, formulas: &HashMap<&str, &Formula>) -> bool {
+    let mut names = formula.expr.references();
+    let mut seen = HashSet::new();
+
+    while let Some(name) = names.pop() {
+        if labels.contains_key(name) || !seen.insert(name) {
+            continue;
+        }
+
+        if let Some(other) = formulas.get(name) {
+            if std::ptr::eq(*other, formula) {
+                return true;
+            }
+            names.extend(other.expr.references());
+        }
+    }
+
+    false
+}
+
+/// All ranges in a span and its subspans with the file they're in.
+fn all_ranges(span: &Span) -> Vec<(&Range, Option<&str>)> {
+    let mut ranges: Vec<_> = span.ranges.iter().map(|range| (range, span.file.as_deref())).collect();
+    for subspan in &span.subspans {
+        ranges.extend(all_ranges(subspan));
+    }
+    ranges
+}
+
+/// Ranges are inclusive in both ends and excluded accounts don't overlap anything. Reversed ranges
+/// are reported on their own so they don't overlap anything either.
+///
+/// The accounts written as plain numbers are compared exactly with `Range::intervals`. There's no
+/// next account after an account like `150` for the others though, `0151` and `150.5` are both
+/// between 150 and 151. So for those we try a few accounts around every entry: its start, the
+/// account right after its end, both of them with a leading zero and, for a pattern like `3*`, the
+/// first account with each number of digits. That finds the overlaps in the lines people write,
+/// but two lines that only share accounts like `150.5` between the ends of their entries aren't
+/// reported.
+fn overlaps(a: &Range, b: &Range) -> bool {
+    if intervals_overlap(&a.intervals(), &b.intervals()) {
+        return true;
+    }
+
+    let entries = || a.accounts.iter().chain(&b.accounts);
+    let longest = entries()
+        .map(|entry| entry.from.as_str().len().max(entry.to.as_str().len()))
+        .max()
+        .unwrap_or(0);
+
+    entries()
+        .flat_map(|entry| candidates(entry, longest))
+        .any(|account| a.contains(&account) && b.contains(&account))
+}
+
+/// Both lists are sorted and don't overlap themselves, so we can walk them side by side.
+fn intervals_overlap(a: &[(u32, u32)], b: &[(u32, u32)]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while let (Some(x), Some(y)) = (a.get(i), b.get(j)) {
+        if x.0 <= y.1 && y.0 <= x.1 {
+            return true;
+        }
+        if x.1 < y.1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    false
+}
+
+fn candidates(entry: &AccountRange, longest: usize) -> Vec<AccountId> {
+    let mut candidates = vec![entry.from.clone()];
+    candidates.extend(next_account(&entry.to));
+    // `0151` comes right before 151
+    let zeros: Vec<AccountId> = candidates.iter().map(|account| AccountId::new(format!("0{}", account))).collect();
+    candidates.extend(zeros);
+
+    if let Some(AccountPattern::Prefix { prefix }) = &entry.pattern {
+        for len in prefix.len() + 1..=longest {
+            let zeros = "0".repeat(len - prefix.len());
+            candidates.push(AccountId::new(format!("{}{}", prefix, zeros)));
+        }
+    }
+
+    candidates
+}
+
+/// The account after `account` if it ends with digits, like `3000` to `3001` or `0199` to `0200`.
+fn next_account(account: &AccountId) -> Option<AccountId> {
+    let text = account.as_str();
+    let head = text.trim_end_matches(|c: char| c.is_ascii_digit());
+    if head.len() == text.len() {
+        return None;
+    }
+
+    let mut digits: Vec<char> = text[head.len()..].chars().collect();
+    for digit in digits.iter_mut().rev() {
+        if *digit != '9' {
+            *digit = (*digit as u8 + 1) as char;
+            return Some(AccountId::new(format!("{}{}", head, digits.iter().collect::<String>())));
+        }
+        *digit = '0';
+    }
+
+    // all nines, like 999 to 1000
+    Some(AccountId::new(format!("{}1{}", head, digits.iter().collect::<String>())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccountSyntax, Parser};
+
+    fn rules(diagnostics: &[Diagnostic]) -> Vec<(Rule, usize)> {
+        diagnostics.iter().map(|d| (d.rule, d.location.start.line)).collect()
+    }
+
+    #[test]
+    fn finds_all_problems() {
+        let test = "\
+Sales (
+    3010..3010 => Webshop
+    3010..4000 => Other sales
+    5000..4000 => Webshop
+) => Sum sales
+Sales (
+    3500..3600 => Misc
+    (
+    )
+) => Sum
+";
+        let spans = Parser::new(test).parse().unwrap();
+        let diagnostics = validate(&spans);
+
+        assert_eq!(rules(&diagnostics), vec![
+            (Rule::OverlapInSpan, 3),
+            (Rule::ReversedRange, 4),
+            (Rule::DuplicateTitle, 4),
+            (Rule::DuplicateTitle, 6),
+            (Rule::OverlapAcrossSpans, 7),
+            (Rule::EmptySpan, 8),
+            (Rule::MissingSumLabel, 9),
+        ]);
+
+        assert_eq!(diagnostics[0].related.unwrap().start.line, 2);
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+    }
+
+    #[test]
+    fn finds_overlaps_with_exclusions_and_patterns() {
+        let test = "\
+(
+    1..10, !1..5, !7..10 => A
+    6 => B
+    3* => C
+    2000..4000 => D
+    A-1..A-9 => E
+    A-5.01 => F
+    30xx, !3000..3099 => G
+) => Sum
+";
+        let spans = Parser::new(test).account_syntax(AccountSyntax::Alphanumeric).parse().unwrap();
+        let diagnostics = validate(&spans);
+
+        assert_eq!(rules(&diagnostics), vec![
+            (Rule::OverlapInSpan, 3),
+            (Rule::OverlapInSpan, 5),
+            (Rule::OverlapInSpan, 7),
+        ]);
+
+        // the only account both lines have is 0151, which is between 150 and 151
+        let test = "(\n    100..200, !100..150 => A\n    150..151, !150, !151 => B\n) => Sum\n";
+        let spans = Parser::new(test).parse().unwrap();
+        assert_eq!(rules(&validate(&spans)), vec![(Rule::OverlapInSpan, 3)]);
+    }
+
+    #[test]
+    fn checks_formulas() {
+        let test = "\
+(
+    3000 => Sales
+) => Sum sales
+Gross profit = Sum sales - Sum material
+Result = Gross profit - Costs
+Costs = Result * 0.5 + Sum sales
+Sum sales = 1
+Fine = Sum sales / Sum sales
+";
+        let spans = Parser::new(test).parse().unwrap();
+        let diagnostics = validate(&spans);
+
+        assert_eq!(rules(&diagnostics), vec![
+            (Rule::UnknownReference, 4),
+            (Rule::CircularReference, 5),
+            (Rule::CircularReference, 6),
+            (Rule::DuplicateTitle, 7),
+        ]);
+        assert_eq!(diagnostics[0].message, "\"Sum material\" in Gross profit isn't a sum label or the title of a formula");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[3].related.unwrap().start.line, 3);
+    }
+
+    #[test]
+    fn checks_range_sets() {
+        let test = "\
+let bank = 1900..1999
+let cash = 1920
+let bank = 1950
+(
+    -@bank, 1800 => Bank
+    @loans, !1960 => Loans
+) => Sum
+";
+        let document = Parser::new(test).parse_document().unwrap();
+        assert!(validate(&document.spans()).is_empty());
+        let diagnostics = validate_document(&document);
+
+        assert_eq!(rules(&diagnostics), vec![
+            (Rule::UnusedRangeSet, 2),
+            (Rule::DuplicateTitle, 3),
+            (Rule::UndefinedRangeSet, 6),
+        ]);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[2].message, "Range set @loans in Loans isn't defined");
+        assert_eq!(diagnostics[2].severity, Severity::Error);
+    }
+
+    #[test]
+    fn checks_templates() {
+        let test = "\
+template department(title, base)
+{title} (
+    {base}00 => Sales
+) => Sum {title}
+template department(title)
+{title} (
+) =>
+(
+    use department(Bergen, 20)
+) => Sum
+";
+        let document = Parser::new(test).parse_document().unwrap();
+        assert!(validate(&document.spans()).is_empty());
+        let diagnostics = validate_document(&document);
+
+        assert_eq!(rules(&diagnostics), vec![(Rule::DuplicateTitle, 5)]);
+        assert_eq!(diagnostics[0].message, "Template department is already defined");
+        assert_eq!(diagnostics[0].related.unwrap().start.line, 1);
+    }
+
+    #[test]
+    fn rules_can_be_configured() {
+        let test = "\
+(
+    3010..3010 => Webshop
+    3010..4000 => Other sales
+) => Sum sales
+";
+        let spans = Parser::new(test).parse().unwrap();
+        assert!(Validator::new().allow(Rule::OverlapInSpan).validate(&spans).is_empty());
+
+        let diagnostics = Validator::new()
+            .rule(Rule::OverlapInSpan, Severity::Error)
+            .validate(&spans);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn reports_with_source_line() {
+        let test = "(\n    4000..3000 => Sales\n) => Sum\n";
+        let spans = Parser::new(test).parse().unwrap();
+        let diagnostics = validate(&spans);
+
+        let expected = "
+line: 2, pos: 5
+    4000..3000 => Sales
+----^
+
+ERROR: Range 4000..3000 is reversed, the start is after the end
+";
+        assert_eq!(diagnostics[0].report(test), expected);
+    }
+}